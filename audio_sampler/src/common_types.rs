@@ -1,5 +1,10 @@
-use audio_sampler_lib::common_types::{LoopMode, NoteOffBehaviour, VersionedWaveformSummary};
+use audio_sampler_lib::common_types::{
+    EnvelopeCurve, Interpolation, LoopMode, NoteOffBehaviour, OutputRouting, PitchTracking,
+    PlaybackMode, RecordingMode, Retrigger, VersionedWaveformSummary, VoiceStealing, WavFormat,
+    DEFAULT_SLICE_COUNT, MAX_VOICE_STORAGE,
+};
 use audio_sampler_lib::sampler::VoiceInfo;
+use audio_sampler_lib::time_value::TimeValue;
 use nih_plug::prelude::Enum;
 use std::convert::TryInto;
 use std::sync::Arc;
@@ -118,6 +123,258 @@ impl From<NoteOffBehaviourParam> for NoteOffBehaviour {
     }
 }
 
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum InterpolationParam {
+    #[name = "None"]
+    None,
+    #[name = "Linear"]
+    Linear,
+    #[name = "Cubic Hermite"]
+    CubicHermite,
+    #[name = "Windowed sinc"]
+    WindowedSinc,
+}
+
+impl From<InterpolationParam> for Interpolation {
+    fn from(param: InterpolationParam) -> Self {
+        match param {
+            InterpolationParam::None => Interpolation::None,
+            InterpolationParam::Linear => Interpolation::Linear,
+            InterpolationParam::CubicHermite => Interpolation::CubicHermite,
+            InterpolationParam::WindowedSinc => Interpolation::WindowedSinc,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum WavFormatParam {
+    #[name = "32-bit float"]
+    Float32,
+    #[name = "24-bit"]
+    Pcm24,
+    #[name = "16-bit"]
+    Pcm16,
+}
+
+impl From<WavFormatParam> for WavFormat {
+    fn from(param: WavFormatParam) -> Self {
+        match param {
+            WavFormatParam::Float32 => WavFormat::Float32,
+            WavFormatParam::Pcm24 => WavFormat::Pcm24,
+            WavFormatParam::Pcm16 => WavFormat::Pcm16,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum PlaybackModeParam {
+    #[name = "Resample"]
+    Resample,
+    #[name = "Granular"]
+    Granular,
+}
+
+impl From<PlaybackModeParam> for PlaybackMode {
+    fn from(param: PlaybackModeParam) -> Self {
+        match param {
+            PlaybackModeParam::Resample => PlaybackMode::Resample,
+            PlaybackModeParam::Granular => PlaybackMode::Granular,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum RetriggerParam {
+    #[name = "Restart"]
+    Restart,
+    #[name = "Layer"]
+    Layer,
+    #[name = "Ignore"]
+    Ignore,
+}
+
+impl From<RetriggerParam> for Retrigger {
+    fn from(param: RetriggerParam) -> Self {
+        match param {
+            RetriggerParam::Restart => Retrigger::Restart,
+            RetriggerParam::Layer => Retrigger::Layer,
+            RetriggerParam::Ignore => Retrigger::Ignore,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum OutputRoutingParam {
+    #[name = "Off"]
+    Off,
+    #[name = "Slice"]
+    Slice,
+    #[name = "Note range"]
+    NoteRange,
+    #[name = "Round robin"]
+    RoundRobin,
+}
+
+impl From<OutputRoutingParam> for OutputRouting {
+    fn from(param: OutputRoutingParam) -> Self {
+        match param {
+            OutputRoutingParam::Off => OutputRouting::Off,
+            OutputRoutingParam::Slice => OutputRouting::Slice,
+            OutputRoutingParam::NoteRange => OutputRouting::NoteRange,
+            OutputRoutingParam::RoundRobin => OutputRouting::RoundRobin,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum PitchTrackingParam {
+    #[name = "Linear"]
+    Linear,
+    #[name = "Exponential"]
+    Exponential,
+}
+
+impl From<PitchTrackingParam> for PitchTracking {
+    fn from(param: PitchTrackingParam) -> Self {
+        match param {
+            PitchTrackingParam::Linear => PitchTracking::Linear,
+            PitchTrackingParam::Exponential => PitchTracking::Exponential,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum SliceCountParam {
+    #[name = "4"]
+    Four,
+    #[name = "8"]
+    Eight,
+    #[name = "16"]
+    Sixteen,
+    #[name = "32"]
+    ThirtyTwo,
+    #[name = "64"]
+    SixtyFour,
+    #[name = "Custom"]
+    Custom,
+}
+
+// Slice count divides the buffer into equal parts, the others slice it by musical length
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum SliceLengthParam {
+    #[name = "Slice count"]
+    SliceCount,
+    #[name = "1/32 note"]
+    ThirtySecondNote,
+    #[name = "1/16 note"]
+    SixteenthNote,
+    #[name = "1/8 note"]
+    EighthNote,
+    #[name = "1/4 note"]
+    QuarterNote,
+    #[name = "1/2 note"]
+    HalfNote,
+    #[name = "1 bar"]
+    Bar,
+}
+
+impl SliceLengthParam {
+    pub fn time_value(&self) -> Option<TimeValue> {
+        match self {
+            SliceLengthParam::SliceCount => None,
+            SliceLengthParam::ThirtySecondNote => Some(TimeValue::quarter_notes(0.125)),
+            SliceLengthParam::SixteenthNote => Some(TimeValue::quarter_notes(0.25)),
+            SliceLengthParam::EighthNote => Some(TimeValue::quarter_notes(0.5)),
+            SliceLengthParam::QuarterNote => Some(TimeValue::quarter_notes(1.0)),
+            SliceLengthParam::HalfNote => Some(TimeValue::quarter_notes(2.0)),
+            SliceLengthParam::Bar => Some(TimeValue::bars(1.0)),
+        }
+    }
+}
+
+// Hold reverses all voices while note 1 is held, Latch toggles reverse for new voices
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum ReverseModeParam {
+    #[name = "Hold"]
+    Hold,
+    #[name = "Latch"]
+    Latch,
+}
+
+// what is recorded and captured, the main input is always what gets passed through
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum InputSourceParam {
+    #[name = "Main"]
+    Main,
+    #[name = "Sidechain"]
+    Sidechain,
+    #[name = "Main + sidechain"]
+    MainAndSidechain,
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum VoiceStealingParam {
+    #[name = "Oldest"]
+    Oldest,
+    #[name = "Quietest"]
+    Quietest,
+    #[name = "Same note"]
+    SameNote,
+    #[name = "Lowest priority"]
+    LowestPriority,
+}
+
+impl From<VoiceStealingParam> for VoiceStealing {
+    fn from(param: VoiceStealingParam) -> Self {
+        match param {
+            VoiceStealingParam::Oldest => VoiceStealing::Oldest,
+            VoiceStealingParam::Quietest => VoiceStealing::Quietest,
+            VoiceStealingParam::SameNote => VoiceStealing::SameNote,
+            VoiceStealingParam::LowestPriority => VoiceStealing::LowestPriority,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum EnvelopeCurveParam {
+    #[name = "Linear"]
+    Linear,
+    #[name = "Exponential"]
+    Exponential,
+    #[name = "S-curve"]
+    SCurve,
+}
+
+impl From<EnvelopeCurveParam> for EnvelopeCurve {
+    fn from(param: EnvelopeCurveParam) -> Self {
+        match param {
+            EnvelopeCurveParam::Linear => EnvelopeCurve::Linear,
+            EnvelopeCurveParam::Exponential => EnvelopeCurve::Exponential,
+            EnvelopeCurveParam::SCurve => EnvelopeCurve::SCurve,
+        }
+    }
+}
+
+#[derive(Debug, Enum, PartialEq, Clone, Copy)]
+pub enum RecordingModeParam {
+    #[name = "Free"]
+    Free,
+    #[name = "Fixed length"]
+    FixedLength,
+    #[name = "Overdub"]
+    Overdub,
+}
+
+impl From<RecordingModeParam> for RecordingMode {
+    fn from(param: RecordingModeParam) -> Self {
+        match param {
+            RecordingModeParam::Free => RecordingMode::Free,
+            RecordingModeParam::FixedLength => RecordingMode::FixedLength,
+            RecordingModeParam::Overdub => RecordingMode::Overdub,
+        }
+    }
+}
+
 #[derive(Debug, Enum, PartialEq, Clone, Copy)]
 pub enum TimeOrRatioUnitParam {
     #[name = "1/16 notes"]
@@ -128,12 +385,71 @@ pub enum TimeOrRatioUnitParam {
     Ratio,
 }
 
-#[derive(Clone, Default, Debug)]
+const NOTES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+// note 0 is C-2
+pub fn note_name(note: i32) -> String {
+    format!(
+        "{}{}",
+        NOTES[note.rem_euclid(12) as usize],
+        note.div_euclid(12) - 2
+    )
+}
+
+// widest layout in AUDIO_IO_LAYOUTS
+const MAX_CHANNELS: usize = 2;
+
+#[derive(Debug)]
 pub struct Info {
     pub voices: Vec<VoiceInfo>,
     pub last_recorded_indices: Vec<Option<usize>>,
     pub data_len: usize,
     pub waveform_summary: Arc<VersionedWaveformSummary>,
+    // grid the editor draws over the waveform
+    pub slice_ratio: f32,
+    pub slice_count: usize,
+    // slices from here on are labelled with the notes that start them
+    pub slice_base_note: u8,
+    pub slice_note_count: usize,
+    // takes and captures that had no spare buffer to go into
+    pub dropped_takes: usize,
+}
+
+// the audio thread fills these in place, they need room for every voice and channel
+impl Default for Info {
+    fn default() -> Self {
+        Self {
+            voices: Vec::with_capacity(MAX_VOICE_STORAGE),
+            last_recorded_indices: Vec::with_capacity(MAX_CHANNELS),
+            data_len: 0,
+            waveform_summary: Default::default(),
+            slice_ratio: 1.0 / DEFAULT_SLICE_COUNT as f32,
+            slice_count: DEFAULT_SLICE_COUNT,
+            slice_base_note: 12,
+            slice_note_count: DEFAULT_SLICE_COUNT,
+            dropped_takes: 0,
+        }
+    }
+}
+
+// derived clone would not keep the capacity, the triple buffer clones the initial value
+impl Clone for Info {
+    fn clone(&self) -> Self {
+        let mut info = Self::default();
+        info.voices.extend_from_slice(&self.voices);
+        info.last_recorded_indices
+            .extend_from_slice(&self.last_recorded_indices);
+        info.data_len = self.data_len;
+        info.waveform_summary = self.waveform_summary.clone();
+        info.slice_ratio = self.slice_ratio;
+        info.slice_count = self.slice_count;
+        info.slice_base_note = self.slice_base_note;
+        info.slice_note_count = self.slice_note_count;
+        info.dropped_takes = self.dropped_takes;
+        info
+    }
 }
 
 impl Default for NoteOffBehaviourParam {
@@ -1,6 +1,6 @@
 use std::cell::Cell;
 use std::f64::consts::PI;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use atomic_float::AtomicF32;
@@ -18,7 +18,8 @@ use nih_plug_vizia::widgets::*;
 use nih_plug_vizia::{assets, create_vizia_editor, ViziaState, ViziaTheming};
 
 use crate::common_types::TimeOrRatioUnitParam;
-use crate::common_types::{Info, NoteOffBehaviourParam};
+use crate::common_types::{note_name, Info, NoteOffBehaviourParam};
+use crate::note_map::{NoteLearn, NoteRole};
 use crate::AudioSamplerParams;
 
 #[derive(Debug, Clone, Default)]
@@ -31,13 +32,49 @@ pub struct Data {
     pub(crate) params: Arc<AudioSamplerParams>,
     pub(crate) debug_data_out: Arc<parking_lot::Mutex<triple_buffer::Output<DebugData>>>,
     pub(crate) peak_meter: Arc<AtomicF32>,
+    pub(crate) export_requested: Arc<AtomicBool>,
+    pub(crate) load_requested: Arc<AtomicBool>,
+    pub(crate) note_learn: Arc<NoteLearn>,
+}
+
+pub enum EditorEvent {
+    UpdateExportDir(String),
+    Export,
+    UpdateImportPath(String),
+    Load,
+    LearnNote(NoteRole),
+    // polled, the audio thread can't set the param of a learned note itself
+    ApplyLearnedNote,
 }
 
 impl Model for Data {
-    //     fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
-    //         event.map(|editor_event, _| match editor_event {
-    //         });
-    //     }
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|editor_event, _| match editor_event {
+            EditorEvent::UpdateExportDir(s) => {
+                *self.params.export_dir.0.lock() = s.to_string();
+            }
+            EditorEvent::Export => {
+                self.export_requested.store(true, Ordering::Relaxed);
+            }
+            EditorEvent::UpdateImportPath(s) => {
+                *self.params.import_path.0.lock() = s.to_string();
+            }
+            EditorEvent::Load => {
+                self.load_requested.store(true, Ordering::Relaxed);
+            }
+            EditorEvent::LearnNote(role) => {
+                self.note_learn.learn(*role);
+            }
+            EditorEvent::ApplyLearnedNote => {
+                if let Some((role, note)) = self.note_learn.take_learned() {
+                    let param = self.params.note_param(role);
+                    cx.emit(ParamEvent::BeginSetParameter(param).upcast());
+                    cx.emit(ParamEvent::SetParameter(param, note as i32).upcast());
+                    cx.emit(ParamEvent::EndSetParameter(param).upcast());
+                }
+            }
+        });
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -58,27 +95,21 @@ struct WaveformView {
     image: Cell<Option<(usize, ImageId)>>,
 }
 
-const NOTES: [&str; 12] = [
-    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
-];
-
-fn display_notes(cx: &mut Context) {
+// note param with a button that assigns the next incoming note instead
+fn note_map_row(cx: &mut Context, role: NoteRole) {
+    Label::new(cx, role.name()).top(Pixels(10.0));
     HStack::new(cx, |cx| {
-        for i in 0..16 {
-            let ocatve = (i as i32 / 12) - 2;
-            let note = NOTES[i % 12].to_string() + ocatve.to_string().as_str();
-
-            Label::new(cx, &note)
-                //.background_color(c)
-                .text_align(TextAlign::Center)
-                .width(Percentage(100.0 / 16.0));
-        }
+        ParamSlider::new(cx, Data::params, move |params| params.note_param(role))
+            .width(Stretch(1.0));
+        Button::new(
+            cx,
+            move |cx| cx.emit(EditorEvent::LearnNote(role)),
+            |cx| Label::new(cx, "Learn"),
+        )
+        .left(Pixels(5.0));
     })
-    .child_left(Stretch(1.0))
-    .child_right(Stretch(1.0))
-    .child_top(Pixels(-20.0))
-    .width(Percentage(100.0))
-    .height(Percentage(100.0));
+    .height(Auto)
+    .right(Pixels(10.0));
 }
 
 impl WaveformView {
@@ -90,9 +121,7 @@ impl WaveformView {
             debug_data: debug_data_lens.get(cx),
             image: Cell::new(None),
         }
-        .build(cx, |cx| {
-            display_notes(cx);
-        })
+        .build(cx, |_| {})
     }
 
     fn get_image(&self, canvas: &mut Canvas, info: &Info) -> ImageId {
@@ -244,11 +273,27 @@ impl View for WaveformView {
 
         canvas.fill_text(0.0, 0.0, "HELLO", &Paint::color(Color::rgb(0, 255, 0)));
 
-        for i in 1..16 {
-            let width = 5.0;
-            let x = i as f32 * (bounds.w / 16.0) + bounds.x;
-            let path = rectangle_path(x, bounds.y + 2.0, width, bounds.h - 4.0);
-            canvas.fill_path(&path, &slice_paint);
+        // the slice grid, labelled with the notes that start each slice
+        let mut label_paint = Paint::color(Color::rgb(0, 0, 0).into());
+        label_paint.set_text_align(vg::Align::Center);
+        let slice_width = info.slice_ratio * bounds.w;
+        for i in 0..info.slice_count {
+            let x = i as f32 * slice_width + bounds.x;
+            if i > 0 {
+                let width = 5.0;
+                let path = rectangle_path(x, bounds.y + 2.0, width, bounds.h - 4.0);
+                canvas.fill_path(&path, &slice_paint);
+            }
+            // labels would overlap on a dense grid
+            if i < info.slice_note_count && slice_width >= 30.0 {
+                let note = note_name(info.slice_base_note as i32 + i as i32);
+                canvas.fill_text(x + slice_width / 2.0, bounds.y - 5.0, note, &label_paint);
+            }
+        }
+
+        if info.dropped_takes > 0 {
+            let text = format!("{} takes dropped, no buffer was ready", info.dropped_takes);
+            canvas.fill_text(bounds.x + 5.0, bounds.y + 15.0, text, &rec_paint);
         }
 
         //if let Some(x) = info.last_recorded_index {
@@ -351,6 +396,12 @@ pub(crate) fn create(editor_state: Arc<ViziaState>, data: Data) -> Option<Box<dy
         register_noto_sans_bold(cx);
 
         data.clone().build(cx);
+        let learn_timer = cx.add_timer(Duration::from_millis(50), None, |cx, action| {
+            if let TimerAction::Tick(_) = action {
+                cx.emit(EditorEvent::ApplyLearnedNote);
+            }
+        });
+        cx.start_timer(learn_timer);
 
         VStack::new(cx, |cx| {
             HStack::new(cx, |cx| {
@@ -376,22 +427,96 @@ pub(crate) fn create(editor_state: Arc<ViziaState>, data: Data) -> Option<Box<dy
             HStack::new(cx, |cx| {
                 VStack::new(cx, |cx| {
                     param_slider1(cx, "Volume", |params| &params.volume);
+                    param_slider1(cx, "Passthru", |params| &params.auto_passthru);
+                    param_slider1(cx, "Export format", |params| &params.export_format);
+                    param_slider1(cx, "Stored audio limit", |params| {
+                        &params.stored_audio_limit
+                    });
+                    param_slider1(cx, "Max recording", |params| &params.max_recording_length);
+                    Label::new(cx, "Export directory").top(Pixels(10.0));
+                    Textbox::new(
+                        cx,
+                        Data::params.map(|params| params.export_dir.0.lock().to_string()),
+                    )
+                    .width(Stretch(1.0))
+                    .right(Pixels(10.0))
+                    .on_edit(|cx, s| cx.emit(EditorEvent::UpdateExportDir(s.to_string())));
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(EditorEvent::Export),
+                        |cx| Label::new(cx, "Export"),
+                    )
+                    .top(Pixels(10.0));
+                    Label::new(cx, "Sample file").top(Pixels(10.0));
+                    Textbox::new(
+                        cx,
+                        Data::params.map(|params| params.import_path.0.lock().to_string()),
+                    )
+                    .width(Stretch(1.0))
+                    .right(Pixels(10.0))
+                    .on_edit(|cx, s| cx.emit(EditorEvent::UpdateImportPath(s.to_string())));
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(EditorEvent::Load),
+                        |cx| Label::new(cx, "Load"),
+                    )
+                    .top(Pixels(10.0));
+                })
+                .width(Percentage(20.0));
+                VStack::new(cx, |cx| {
                     param_slider1(cx, "Attack", |params| &params.attack);
+                    param_slider1(cx, "Hold", |params| &params.hold);
                     param_slider1(cx, "Decay", |params| &params.decay);
-                    param_slider1(cx, "Passthru", |params| &params.auto_passthru);
+                    param_slider1(cx, "Sustain", |params| &params.sustain);
+                    param_slider1(cx, "Release", |params| &params.release);
+                    param_slider1(cx, "Envelope curve", |params| &params.envelope_curve);
                 })
-                .width(Percentage(25.0));
+                .width(Percentage(20.0));
                 VStack::new(cx, |cx| {
                     param_slider1(cx, "Speed", |params| &params.speed);
+                    param_slider1(cx, "Pitch bend range", |params| &params.pitch_bend_range);
+                    param_slider1(cx, "Scratch CC", |params| &params.scratch_cc);
+                    param_slider1(cx, "Scratch inertia", |params| &params.scratch_inertia);
+                    param_slider1(cx, "Playback mode", |params| &params.playback_mode);
+                    param_slider1(cx, "Pitch", |params| &params.pitch);
+                    param_slider1(cx, "Grain size", |params| &params.grain_size);
                     //param_slider1(cx, "Start offset", |params| &params.start_offset);
                     param_slider1(cx, "Loop mode", |params| &params.loop_mode);
+                    param_slider1(cx, "Loop crossfade", |params| &params.loop_crossfade);
                     param_slider1(cx, "Note off behaviour", |params| &params.note_off_behavior);
+                    param_slider1(cx, "Interpolation", |params| &params.interpolation);
+                    param_slider1(cx, "Max voices", |params| &params.max_voices);
+                    param_slider1(cx, "Voice stealing", |params| &params.voice_stealing);
+                    param_slider1(cx, "Retrigger", |params| &params.retrigger);
+                    param_slider1(cx, "Pitch tracking", |params| &params.pitch_tracking);
+                    param_slider1(cx, "Root note", |params| &params.root_note);
+                    param_slider1(cx, "Reverse mode", |params| &params.reverse_mode);
+                    param_slider1(cx, "Reverse velocity", |params| &params.reverse_velocity);
+                    param_slider1(cx, "Output routing", |params| &params.output_routing);
+                    param_slider1(cx, "Notes per output", |params| &params.notes_per_output);
                 })
-                .width(Percentage(25.0));
+                .width(Percentage(20.0));
                 VStack::new(cx, |cx| {
                     param_slider1(cx, "MIDI channel", |params| &params.midi_channel);
+                    param_slider1(cx, "Sample slot", |params| &params.sample_slot);
+                    param_slider1(cx, "Input source", |params| &params.input_source);
+                    param_slider1(cx, "Recording mode", |params| &params.recording_mode);
+                    param_slider1(cx, "Recording length", |params| {
+                        &params.recording_length_bars
+                    });
+                    param_slider1(cx, "Overdub feedback", |params| &params.overdub_feedback);
+                    param_slider1(cx, "Onset sensitivity", |params| &params.onset_sensitivity);
+                    param_slider1(cx, "Slice count", |params| &params.slice_count);
+                    param_slider1(cx, "Custom slice count", |params| {
+                        &params.custom_slice_count
+                    });
+                    param_slider1(cx, "Slice length", |params| &params.slice_length);
+                    param_slider1(cx, "Capture length", |params| &params.capture_length_bars);
+                    for role in NoteRole::ALL {
+                        note_map_row(cx, role);
+                    }
                 })
-                .width(Percentage(25.0));
+                .width(Percentage(20.0));
 
                 VStack::new(cx, |cx| {
                     loop_length_slider(cx, TimeOrRatioUnitParam::Ratio, |params| {
@@ -16,14 +16,30 @@ use mimalloc::MiMalloc;
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use crate::common_types::{
-    Info, LoopModeParam, MIDIChannelParam, NoteOffBehaviourParam, TimeOrRatioUnitParam,
+    note_name, EnvelopeCurveParam, InputSourceParam, InterpolationParam, LoopModeParam,
+    MIDIChannelParam, NoteOffBehaviourParam, OutputRoutingParam, PitchTrackingParam,
+    PlaybackModeParam, RecordingModeParam, RetriggerParam, ReverseModeParam, SliceCountParam,
+    SliceLengthParam, TimeOrRatioUnitParam, VoiceStealingParam, WavFormatParam,
 };
+use crate::note_map::{NoteAction, NoteLearn, NoteMap, NoteRole};
+use crate::persisted_audio::PersistedAudio;
+use crate::persisted_path::PersistedPath;
+use crate::tasks::{LoadedFile, PendingLoad, PendingSlices, PendingSpare, Task};
+use audio_sampler_lib::audio_file;
 use audio_sampler_lib::common_types::Params as SamplerParams;
-use audio_sampler_lib::common_types::{InitParams, Note, VersionedWaveformSummary};
+use audio_sampler_lib::common_types::{
+    Command, InitParams, Note, PitchTracking, PlaybackMode, VersionedWaveformSummary,
+    DEFAULT_SLOT_COUNT,
+};
 use audio_sampler_lib::sampler::Sampler;
+use audio_sampler_lib::scratch::Scratch;
+use audio_sampler_lib::shared::{SharedSlot, INLINE_CHANNELS, MAX_SLICES};
+use audio_sampler_lib::snapshot;
+use audio_sampler_lib::snapshot::SlotSnapshot;
 use audio_sampler_lib::time_value::{TimeOrRatio, TimeValue};
 use nih_plug::prelude::*;
 #[cfg(feature = "use_vizia")]
@@ -33,12 +49,17 @@ use nih_plug_vizia::vizia::prelude::Role::Time;
 #[cfg(feature = "use_vizia")]
 use nih_plug_vizia::ViziaState;
 use num_traits::ToPrimitive;
+use smallvec::SmallVec;
 
 #[cfg(feature = "use_vizia")]
 use crate::editor_vizia::DebugData;
 
 // mod editor;
 mod common_types;
+mod note_map;
+mod persisted_audio;
+mod persisted_path;
+mod tasks;
 
 #[cfg(feature = "use_vizia")]
 mod editor_vizia;
@@ -62,12 +83,68 @@ pub struct AudioSampler {
     last_frame_recorded: usize,
     last_waveform_updated: usize,
     active_notes: [[i16; 256]; 16],
+    // what each held note started, so note-off ends it even if the note map changed since
+    held_notes: [[HeldNote; 256]; 16],
     iteration: usize,
     reversing: bool,
+    // toggled by note 1 in Latch reverse mode, new voices play backwards while set
+    reverse_latched: bool,
+    // speed ratio from the last pitch bend
+    bend: f32,
+    scratch: Scratch,
+    // offset within the buffer the scratch control has been advanced to
+    scratch_pos: usize,
+    selected_slot: usize,
+    last_sample_slot_param: i32,
+    // set by the editor's export button and the export note
+    export_requested: Arc<AtomicBool>,
+    // set by the editor's load button
+    load_requested: Arc<AtomicBool>,
+    note_learn: Arc<NoteLearn>,
+    pending_load: PendingLoad,
+    pending_spare: PendingSpare,
+    // a spare is being prepared by the background task
+    spare_requested: bool,
+    max_recording_samples: usize,
+    pending_slices: PendingSlices,
+    // onsets of a slot are being detected by the background task
+    slices_requested: bool,
+    // persisted slots waiting to be restored on the first buffer
+    pending_restore: Vec<(usize, SharedSlot)>,
+    persisted_data_version: usize,
+    // scratch buffers sized in initialize so processing doesn't allocate
+    inputs: Vec<Vec<f32>>,
+    record_inputs: Vec<Vec<f32>>,
+    commands: Vec<(usize, Command)>,
+    speed_values: Vec<f32>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum HeldNote {
+    None,
+    Record,
+    Reverse,
+    Voice,
 }
 
 const PEAK_METER_DECAY_MS: f64 = 150.0;
 
+// longest capture and the slowest tempo the capture buffer holds it at
+const MAX_CAPTURE_BARS: i32 = 8;
+const MIN_CAPTURE_TEMPO: f32 = 40.0;
+// bars of 4/4, with one more for the part of a bar played since the last bar line
+const CAPTURE_BUFFER_SECONDS: f32 = (MAX_CAPTURE_BARS + 1) as f32 * 4.0 * 60.0 / MIN_CAPTURE_TEMPO;
+
+// stereo aux outputs voices can be routed to, the main output always has the full mix
+const AUX_OUTPUT_COUNT: usize = 4;
+const MAX_AUX_CHANNELS: usize = AUX_OUTPUT_COUNT * 2;
+
+// how often a smoothed speed change is passed to the sampler within a block
+const SPEED_UPDATE_SAMPLES: usize = 32;
+
+// upper bound on commands per block before the list has to grow
+const MAX_BLOCK_COMMANDS: usize = 1024;
+
 impl Plugin for AudioSampler {
     const NAME: &'static str = "Audio Sampler";
     const VENDOR: &'static str = "seunje";
@@ -75,6 +152,29 @@ impl Plugin for AudioSampler {
     const EMAIL: &'static str = "";
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
     const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[new_nonzero_u32(2); AUX_OUTPUT_COUNT],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                aux_outputs: &["Output 2", "Output 3", "Output 4", "Output 5"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
+            names: PortNames {
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        },
         AudioIOLayout {
             main_input_channels: NonZeroU32::new(2),
             main_output_channels: NonZeroU32::new(2),
@@ -93,13 +193,20 @@ impl Plugin for AudioSampler {
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
     type SysExMessage = SysEx;
 
-    type BackgroundTask = ();
+    type BackgroundTask = Task;
+
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        Box::new(|task| task.run())
+    }
 
     fn reset(&mut self) {
         self.last_waveform_updated = 0;
         self.last_frame_recorded = 0;
         self.sampler.reset();
         self.active_notes.iter_mut().for_each(|v| v.fill(0));
+        self.held_notes
+            .iter_mut()
+            .for_each(|v| v.fill(HeldNote::None));
     }
 
     fn params(&self) -> Arc<dyn Params> {
@@ -114,6 +221,9 @@ impl Plugin for AudioSampler {
             params: self.params.clone(),
             debug_data_out: self.debug_data_out.clone(),
             peak_meter: self.peak_meter.clone(),
+            export_requested: self.export_requested.clone(),
+            load_requested: self.load_requested.clone(),
+            note_learn: self.note_learn.clone(),
         };
 
         editor_vizia::create(self.params.editor_state.clone(), data)
@@ -127,144 +237,333 @@ impl Plugin for AudioSampler {
     ) -> bool {
         self.audio_io_layout = audio_io_layout.clone();
         self.sample_rate = buffer_config.sample_rate;
-        self.sampler = Sampler::new(self.channel_count(), &InitParams::default());
+        self.max_recording_samples =
+            (self.params.max_recording_length.value() as f32 * self.sample_rate) as usize;
+        // the new sampler starts out with a spare of its own
+        *self.pending_spare.lock() = None;
+        self.spare_requested = false;
+        *self.pending_slices.lock() = None;
+        self.slices_requested = false;
+        let previous = std::mem::replace(
+            &mut self.sampler,
+            Sampler::new(
+                self.channel_count(),
+                &InitParams {
+                    capture_buffer_samples: (CAPTURE_BUFFER_SECONDS * self.sample_rate) as usize,
+                    max_recording_samples: self.max_recording_samples,
+                    sample_rate: self.sample_rate,
+                    ..InitParams::default()
+                },
+            ),
+        );
         self.peak_meter_decay_weight = 0.25f64
             .powf((buffer_config.sample_rate as f64 * PEAK_METER_DECAY_MS / 1000.0).recip())
             as f32;
+        let max_buffer_size = buffer_config.max_buffer_size as usize;
+        self.inputs = vec![Vec::with_capacity(max_buffer_size); self.channel_count()];
+        self.record_inputs = vec![Vec::with_capacity(max_buffer_size); self.channel_count()];
+        self.commands = Vec::with_capacity(MAX_BLOCK_COMMANDS);
+        self.speed_values = vec![1.0; max_buffer_size];
+        self.persisted_data_version = self.sampler.get_data_version();
+        self.load_import_path();
+        self.prepare_persisted_audio(previous.snapshot_slots(usize::MAX));
         true
     }
 
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
+        aux: &mut AuxiliaryBuffers,
         context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        let mut next_event = context.next_event();
-
-        for (sample_id, channel_samples) in buffer.iter_samples().enumerate() {
-            let mut params = self.sampler_params(sample_id, &context.transport());
-            let params_midi_channel: Option<u8> = self.params.midi_channel.value().try_into().ok();
-            let params = &mut params;
-            while let Some(event) = next_event {
-                if event.timing() != sample_id as u32 {
-                    break;
+        let num_samples = buffer.samples();
+        self.update_selected_slot_from_param();
+        self.params
+            .speed
+            .smoothed
+            .next_block(&mut self.speed_values[..num_samples], num_samples);
+        let mut params = self.sampler_params(num_samples, &context.transport());
+        self.scratch_pos = 0;
+        let (speed, pitch) = self.bend_and_scratch();
+        params.speed *= speed;
+        params.pitch *= pitch;
+        let params_midi_channel: Option<u8> = self.params.midi_channel.value().try_into().ok();
+        let note_map = self.note_map();
+        self.apply_pending_load();
+        if !self.pending_restore.is_empty() {
+            // draining keeps the list, the buffers move into the sampler
+            for (slot, data) in self.pending_restore.drain(..) {
+                self.sampler.replace_slot(slot, data);
+            }
+            #[cfg(feature = "use_vizia")]
+            self.update_waveform();
+        }
+
+        // notes become commands applied at their offsets by the sampler
+        self.commands.clear();
+        let mut next_speed_update =
+            if self.params.speed.smoothed.is_smoothing() || self.scratch.is_moving() {
+                SPEED_UPDATE_SAMPLES
+            } else {
+                num_samples
+            };
+        let mut capture_committed = false;
+        while let Some(event) = context.next_event() {
+            let offset = (event.timing() as usize).min(num_samples);
+            self.push_speed_updates(&mut next_speed_update, offset);
+            #[cfg(debug_assertions)]
+            nih_warn!("event: iteration={:<20} {:?}", self.iteration, event);
+
+            match event {
+                NoteEvent::MidiPitchBend {
+                    channel: note_channel,
+                    value,
+                    ..
+                } if params_midi_channel.is_none() || params_midi_channel == Some(note_channel) => {
+                    let range = self.params.pitch_bend_range.value() as f32;
+                    self.bend = 2.0_f32.powf((value - 0.5) * 2.0 * range / 12.0);
+                    self.push_speed_update(offset, num_samples);
                 }
-                #[cfg(debug_assertions)]
-                nih_warn!("event: iteration={:<20} {:?}", self.iteration, event);
-
-                match event {
-                    NoteEvent::PolyTuning {
-                        note,
-                        channel: note_channel,
-                        tuning,
-                        ..
-                    } if params_midi_channel.is_none()
-                        || params_midi_channel == Some(note_channel) =>
-                    {
-                        let note = Note::new(note, note_channel);
-                        let mult = self.params.pitch_speed_multiplier.value();
-                        let speed = 1.0 + (tuning / 12.0) * mult;
-                        self.sampler.set_note_speed(note, speed);
+                NoteEvent::MidiCC {
+                    channel: note_channel,
+                    cc,
+                    value,
+                    ..
+                } if (params_midi_channel.is_none()
+                    || params_midi_channel == Some(note_channel))
+                    && cc as i32 == self.params.scratch_cc.value() =>
+                {
+                    self.advance_scratch(offset);
+                    self.scratch.set_value(value);
+                    self.push_speed_update(offset, num_samples);
+                    // keeps following the platter while it moves
+                    if next_speed_update >= num_samples {
+                        next_speed_update = offset + SPEED_UPDATE_SAMPLES;
                     }
-                    NoteEvent::NoteOn {
-                        velocity,
-                        note,
-                        channel: note_channel,
-                        ..
-                    } if params_midi_channel.is_none()
-                        || params_midi_channel == Some(note_channel) =>
-                    {
-                        let note = Note::new(note, note_channel);
-                        match note.note {
-                            0 => {
-                                self.set_note_active(&note, true);
-                                self.sampler.start_recording();
-                            }
-                            1 => {
-                                self.set_note_active(&note, true);
+                }
+                NoteEvent::PolyTuning {
+                    note,
+                    channel: note_channel,
+                    tuning,
+                    ..
+                } if params_midi_channel.is_none() || params_midi_channel == Some(note_channel) => {
+                    let note = Note::new(note, note_channel);
+                    let mult = self.params.pitch_speed_multiplier.value();
+                    // tuning is relative to the note, chromatic notes keep their own pitch
+                    let semitones = self
+                        .chromatic_semitones(&note_map, note.note)
+                        .unwrap_or(0.0)
+                        + tuning * mult;
+                    let speed = self.pitch_tracking().speed(semitones, 1.0);
+                    self.commands
+                        .push((offset, Command::SetNoteSpeed { note, speed }));
+                }
+                NoteEvent::NoteOn {
+                    velocity,
+                    note,
+                    channel: note_channel,
+                    ..
+                } if params_midi_channel.is_none() || params_midi_channel == Some(note_channel) => {
+                    let note = Note::new(note, note_channel);
+                    // while learning the note is assigned instead of played
+                    if self.note_learn.try_learn(note.note) {
+                        continue;
+                    }
+                    match note_map.action(note.note) {
+                        Some(NoteAction::Record) => {
+                            self.hold_note(&note, HeldNote::Record);
+                            self.commands.push((offset, Command::StartRecording));
+                        }
+                        Some(NoteAction::Reverse) => match self.params.reverse_mode.value() {
+                            ReverseModeParam::Hold => {
+                                self.hold_note(&note, HeldNote::Reverse);
                                 self.reversing = true;
-                                params.reverse_speed = -1.0;
+                                self.commands
+                                    .push((offset, Command::SetReverse { reverse: true }));
                             }
-                            12..=27 => {
-                                self.set_note_active(&note, true);
-                                let pos = (note.note - 12) as f32 / 16.0;
-                                self.sampler.start_playing(pos, note, velocity, params);
+                            ReverseModeParam::Latch => self.reverse_latched = !self.reverse_latched,
+                        },
+                        Some(NoteAction::Slice { slice, reverse }) => {
+                            self.hold_note(&note, HeldNote::Voice);
+                            let reverse = reverse || self.starts_reversed(velocity);
+                            self.commands.push((
+                                offset,
+                                Command::StartPlayingSlice {
+                                    slice,
+                                    note,
+                                    velocity,
+                                    reverse,
+                                },
+                            ));
+                        }
+                        Some(NoteAction::Capture) => {
+                            self.commands.push((offset, Command::CommitCapture));
+                            capture_committed = true;
+                        }
+                        Some(NoteAction::Export) => {
+                            self.export_requested.store(true, Ordering::Relaxed)
+                        }
+                        Some(NoteAction::SelectSlot { slot }) => {
+                            self.select_slot(slot);
+                            self.commands.push((
+                                offset,
+                                Command::SelectSlot {
+                                    slot: self.selected_slot,
+                                },
+                            ));
+                        }
+                        // chromatic notes only play in Exponential pitch tracking
+                        Some(NoteAction::Chromatic) => {
+                            if let Some(semitones) = self.chromatic_semitones(&note_map, note.note)
+                            {
+                                self.hold_note(&note, HeldNote::Voice);
+                                let reverse = self.starts_reversed(velocity);
+                                let speed = self.pitch_tracking().speed(semitones, 1.0);
+                                self.commands.push((
+                                    offset,
+                                    Command::StartPlayingWhole {
+                                        note,
+                                        velocity,
+                                        reverse,
+                                    },
+                                ));
+                                self.commands
+                                    .push((offset, Command::SetNoteSpeed { note, speed }));
                             }
-                            _ => (),
-                        };
-                    }
-                    NoteEvent::NoteOff {
-                        note,
-                        channel: note_channel,
-                        ..
-                    } => {
-                        let note = Note::new(note, note_channel);
-                        if self.is_note_active(&note) {
-                            match note.note {
-                                0 => self.sampler.stop_recording(params),
-                                1 => {
-                                    self.reversing = false;
-                                    params.reverse_speed = 1.0;
-                                }
-                                12..=27 => self.sampler.stop_playing(note, params),
-                                _ => (),
+                        }
+                        None => (),
+                    };
+                }
+                NoteEvent::NoteOff {
+                    note,
+                    channel: note_channel,
+                    ..
+                } => {
+                    let note = Note::new(note, note_channel);
+                    if self.is_note_active(&note) {
+                        match self.held_notes[note.channel as usize][note.note as usize] {
+                            HeldNote::Record => {
+                                self.commands.push((offset, Command::StopRecording))
+                            }
+                            HeldNote::Reverse => {
+                                self.reversing = false;
+                                self.commands
+                                    .push((offset, Command::SetReverse { reverse: false }));
                             }
-                            self.set_note_active(&note, false);
+                            // slices and chromatic notes
+                            HeldNote::Voice => {
+                                self.commands.push((offset, Command::StopPlaying { note }))
+                            }
+                            HeldNote::None => (),
                         }
+                        self.set_note_active(&note, false);
                     }
-                    _ => (),
                 }
-
-                #[cfg(debug_assertions)]
-                self.verify_active_notes();
-
-                next_event = context.next_event();
+                _ => (),
             }
-
-            if self.sampler.is_recording(0) {
-                self.last_frame_recorded = self.sampler.get_frames_processed(0);
+        }
+        self.push_speed_updates(&mut next_speed_update, num_samples.saturating_sub(1));
+        self.advance_scratch(num_samples);
+
+        // the buffer is processed in place, the sampler needs its input separately
+        let outputs = buffer.as_slice();
+        for (input, output) in self.inputs.iter_mut().zip(outputs.iter()) {
+            input.clear();
+            input.extend_from_slice(output);
+        }
+        let sidechain = aux
+            .inputs
+            .first_mut()
+            .map(|buffer| buffer.as_slice_immutable());
+        let record_inputs = match (self.params.input_source.value(), sidechain) {
+            (InputSourceParam::Main, _) | (_, None) => &self.inputs,
+            (source, Some(sidechain)) => {
+                for (i, record_input) in self.record_inputs.iter_mut().enumerate() {
+                    // mono tracks record the first sidechain channel
+                    let sidechain = &sidechain[i.min(sidechain.len() - 1)];
+                    record_input.clear();
+                    if source == InputSourceParam::MainAndSidechain {
+                        let main = &self.inputs[i];
+                        record_input.extend(main.iter().zip(sidechain.iter()).map(|(a, b)| a + b));
+                    } else {
+                        record_input.extend_from_slice(sidechain);
+                    }
+                }
+                &self.record_inputs
             }
+        };
+        // every channel of every aux output in turn, the layout the sampler expects
+        let mut aux_outputs: SmallVec<&mut [f32], MAX_AUX_CHANNELS> = aux
+            .outputs
+            .iter_mut()
+            .flat_map(|buffer| buffer.as_slice().iter_mut().map(|channel| &mut **channel))
+            .collect();
+        self.sampler.process_block_with_aux_outputs(
+            &self.inputs,
+            record_inputs,
+            outputs,
+            &mut aux_outputs,
+            &self.commands,
+            &params,
+        );
+
+        #[cfg(debug_assertions)]
+        self.verify_active_notes();
+
+        if self.sampler.is_recording(0) {
+            self.last_frame_recorded = self.sampler.get_frames_processed(0);
+        }
+        #[cfg(feature = "use_vizia")]
+        if capture_committed {
+            self.update_waveform();
+        }
 
-            let mut frame = channel_samples.into_iter().collect::<Vec<_>>();
-            self.sampler.process_frame(&mut frame, params);
-
-            //for sample in channel_samples {
-            //    amplitude += *sample;
-            //}
-            #[cfg(feature = "use_vizia")]
-            if self.params.editor_state.is_open() {
-                self.update_peak_meter(&mut frame);
-
-                if self.last_frame_recorded > self.last_waveform_updated + self.sample_rate as usize
-                {
-                    self.update_waveform();
-                    self.last_waveform_updated = self.last_frame_recorded;
-                }
-                let voice_info = self.sampler.get_voice_info(0, params);
-                let info = Info {
-                    voices: voice_info,
-                    last_recorded_indices: self.sampler.get_last_recorded_offsets(),
-                    data_len: self.sampler.get_data_len(0),
-                    waveform_summary: self.waveform_summary.clone(),
-                };
-                self.debug_data_in.lock().write(DebugData { info });
-
-                let amplitude = (frame.iter().fold(0.0, |z, x| z + **x) / frame.len() as f32).abs();
-
-                let current_peak_meter = self.peak_meter.load(std::sync::atomic::Ordering::Relaxed);
-                let new_peak_meter = if amplitude > current_peak_meter {
-                    amplitude
-                } else {
-                    current_peak_meter * self.peak_meter_decay_weight
-                        + amplitude * (1.0 - self.peak_meter_decay_weight)
-                };
+        #[cfg(feature = "use_vizia")]
+        if self.params.editor_state.is_open() {
+            for i in 0..num_samples {
+                let sum: f32 = outputs.iter().map(|output| output[i]).sum();
+                self.update_peak_meter((sum / outputs.len() as f32).abs());
+            }
 
-                self.peak_meter
-                    .store(new_peak_meter, std::sync::atomic::Ordering::Relaxed)
+            if self.last_frame_recorded > self.last_waveform_updated + self.sample_rate as usize {
+                self.update_waveform();
+                self.last_waveform_updated = self.last_frame_recorded;
             }
+            // updated in place so the buffers are reused
+            let mut debug_data_in = self.debug_data_in.lock();
+            let info = &mut debug_data_in.input_buffer().info;
+            self.sampler
+                .get_voice_info_into(0, &params, &mut info.voices);
+            info.last_recorded_indices.clear();
+            info.last_recorded_indices
+                .extend(self.sampler.iter_last_recorded_offsets());
+            info.data_len = self.sampler.get_data_len(0, self.selected_slot);
+            info.waveform_summary = self.waveform_summary.clone();
+            info.slice_ratio = params.slice_ratio(info.data_len);
+            info.slice_count = params.slice_count(info.data_len);
+            info.slice_base_note = note_map.slice_base;
+            info.slice_note_count = note_map.slice_count as usize;
+            info.dropped_takes = self.sampler.dropped_take_count();
+            debug_data_in.publish();
         }
 
+        if self.export_requested.load(Ordering::Relaxed) {
+            self.export_wav(context);
+        }
+        if self.load_requested.swap(false, Ordering::Relaxed) {
+            context.execute_background(Task::LoadFile {
+                path: self.params.import_path.clone(),
+                slot: self.selected_slot,
+                channel_count: self.channel_count(),
+                sample_rate: self.sample_rate,
+                max_len: self.max_recording_samples,
+                sensitivity: self.params.onset_sensitivity.value(),
+                pending: self.pending_load.clone(),
+            });
+        }
+        self.update_persisted_audio(context);
+        self.update_buffers(context);
+
         self.iteration += 1;
         ProcessStatus::Normal
     }
@@ -281,9 +580,22 @@ pub struct AudioSamplerParams {
     #[id = "attack"]
     pub attack: FloatParam,
 
-    #[id = "decay"]
+    #[id = "hold"]
+    pub hold: FloatParam,
+
+    #[id = "env_decay"]
     pub decay: FloatParam,
 
+    #[id = "sustain"]
+    pub sustain: FloatParam,
+
+    // id is kept from when this was the only note-off fade, so saved projects keep their value
+    #[id = "decay"]
+    pub release: FloatParam,
+
+    #[id = "envelope_curve"]
+    pub envelope_curve: EnumParam<EnvelopeCurveParam>,
+
     #[id = "loop_mode"]
     pub loop_mode: EnumParam<LoopModeParam>,
 
@@ -313,11 +625,136 @@ pub struct AudioSamplerParams {
     #[id = "pitch_speed_multiplier"]
     pub pitch_speed_multiplier: FloatParam,
 
+    #[id = "pitch_tracking"]
+    pub pitch_tracking: EnumParam<PitchTrackingParam>,
+
+    // same default as chromatic_base_note, so the first chromatic note plays at the original
+    // speed
+    #[id = "root_note"]
+    pub root_note: IntParam,
+
+    // in Exponential pitch tracking notes from here up play the whole buffer, pitched by note,
+    // unless the note map gives them another role
+    #[id = "chromatic_base_note"]
+    pub chromatic_base_note: IntParam,
+
+    #[id = "pitch_bend_range"]
+    pub pitch_bend_range: IntParam,
+
+    #[id = "scratch_cc"]
+    pub scratch_cc: IntParam,
+
+    #[id = "scratch_inertia"]
+    pub scratch_inertia: FloatParam,
+
+    #[id = "interpolation"]
+    pub interpolation: EnumParam<InterpolationParam>,
+
+    #[id = "sample_slot"]
+    pub sample_slot: IntParam,
+
+    #[id = "input_source"]
+    pub input_source: EnumParam<InputSourceParam>,
+
+    #[id = "recording_mode"]
+    pub recording_mode: EnumParam<RecordingModeParam>,
+
+    #[id = "recording_length_bars"]
+    pub recording_length_bars: IntParam,
+
+    #[id = "overdub_feedback"]
+    pub overdub_feedback: FloatParam,
+
+    #[id = "capture_length_bars"]
+    pub capture_length_bars: IntParam,
+
+    #[id = "output_routing"]
+    pub output_routing: EnumParam<OutputRoutingParam>,
+
+    #[id = "notes_per_output"]
+    pub notes_per_output: IntParam,
+
+    #[id = "reverse_mode"]
+    pub reverse_mode: EnumParam<ReverseModeParam>,
+
+    #[id = "reverse_velocity"]
+    pub reverse_velocity: FloatParam,
+
+    #[id = "max_voices"]
+    pub max_voices: IntParam,
+
+    #[id = "voice_stealing"]
+    pub voice_stealing: EnumParam<VoiceStealingParam>,
+
+    #[id = "retrigger"]
+    pub retrigger: EnumParam<RetriggerParam>,
+
+    #[id = "playback_mode"]
+    pub playback_mode: EnumParam<PlaybackModeParam>,
+
+    #[id = "pitch"]
+    pub pitch: FloatParam,
+
+    #[id = "grain_size"]
+    pub grain_size: FloatParam,
+
+    #[id = "loop_crossfade"]
+    pub loop_crossfade: FloatParam,
+
+    #[id = "onset_sensitivity"]
+    pub onset_sensitivity: FloatParam,
+
+    #[id = "slice_count"]
+    pub slice_count: EnumParam<SliceCountParam>,
+
+    #[id = "custom_slice_count"]
+    pub custom_slice_count: IntParam,
+
+    #[id = "slice_length"]
+    pub slice_length: EnumParam<SliceLengthParam>,
+
+    #[id = "record_note"]
+    pub record_note: IntParam,
+
+    #[id = "reverse_note"]
+    pub reverse_note: IntParam,
+
+    #[id = "capture_note"]
+    pub capture_note: IntParam,
+
+    #[id = "export_note"]
+    pub export_note: IntParam,
+
+    #[id = "slice_base_note"]
+    pub slice_base_note: IntParam,
+
+    #[id = "slot_base_note"]
+    pub slot_base_note: IntParam,
+
     /// The editor state, saved together with the parameter state so the custom scaling can be
     /// restored.
     #[cfg(feature = "use_vizia")]
     #[persist = "editor-state"]
     editor_state: Arc<ViziaState>,
+
+    #[id = "export_format"]
+    pub export_format: EnumParam<WavFormatParam>,
+
+    #[persist = "export-dir"]
+    pub export_dir: PersistedPath,
+
+    #[persist = "import-path"]
+    pub import_path: PersistedPath,
+
+    #[persist = "recorded-audio"]
+    pub recorded_audio: PersistedAudio,
+
+    #[id = "stored_audio_limit"]
+    pub stored_audio_limit: IntParam,
+
+    // memory is allocated up front, changes apply when the plugin is next initialized
+    #[id = "max_recording_length"]
+    pub max_recording_length: IntParam,
 }
 
 const ATTACK_DECAY_SKEW_FACTOR: f32 = 0.25;
@@ -348,8 +785,29 @@ impl Default for AudioSamplerParams {
                 },
             )
             .with_unit(" ms"),
+            hold: FloatParam::new(
+                "Hold",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1000.0,
+                    factor: ATTACK_DECAY_SKEW_FACTOR,
+                },
+            )
+            .with_unit(" ms"),
             decay: FloatParam::new(
                 "Decay",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1000.0,
+                    factor: ATTACK_DECAY_SKEW_FACTOR,
+                },
+            )
+            .with_unit(" ms"),
+            sustain: FloatParam::new("Sustain", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            release: FloatParam::new(
+                "Release",
                 0.1,
                 FloatRange::Skewed {
                     min: 0.0,
@@ -358,12 +816,40 @@ impl Default for AudioSamplerParams {
                 },
             )
             .with_unit(" ms"),
+            envelope_curve: EnumParam::new("Envelope curve", EnvelopeCurveParam::Linear),
             midi_channel: EnumParam::new("MIDI channel", MIDIChannelParam::All),
             pitch_speed_multiplier: FloatParam::new(
                 "Pitch Speed Multiplier",
                 1.0,
                 FloatRange::Linear { min: 0.1, max: 2.0 },
             ),
+            pitch_tracking: EnumParam::new("Pitch tracking", PitchTrackingParam::Linear),
+            root_note: IntParam::new("Root note", 60, IntRange::Linear { min: 0, max: 127 })
+                .with_value_to_string(Arc::new(note_name)),
+            chromatic_base_note: IntParam::new(
+                "Chromatic base note",
+                60,
+                IntRange::Linear { min: 0, max: 127 },
+            )
+            .with_value_to_string(Arc::new(note_name)),
+            pitch_bend_range: IntParam::new(
+                "Pitch bend range",
+                2,
+                IntRange::Linear { min: 0, max: 24 },
+            )
+            .with_unit(" st"),
+            // the mod wheel, which rests at 0 where scratching has no effect
+            scratch_cc: IntParam::new("Scratch CC", 1, IntRange::Linear { min: 0, max: 127 }),
+            scratch_inertia: FloatParam::new(
+                "Scratch inertia",
+                100.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1000.0,
+                    factor: ATTACK_DECAY_SKEW_FACTOR,
+                },
+            )
+            .with_unit(" ms"),
             loop_mode: EnumParam::new("Loop mode", LoopModeParam::Loop),
             loop_length_unit: EnumParam::new("Loop length unit", TimeOrRatioUnitParam::Ratio),
             loop_length_percent: FloatParam::new(
@@ -407,6 +893,155 @@ impl Default for AudioSamplerParams {
                 "Note off behavior",
                 NoteOffBehaviourParam::default(),
             ),
+            interpolation: EnumParam::new("Interpolation", InterpolationParam::Linear),
+            sample_slot: IntParam::new(
+                "Sample slot",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: DEFAULT_SLOT_COUNT as i32,
+                },
+            ),
+            input_source: EnumParam::new("Input source", InputSourceParam::Main),
+            recording_mode: EnumParam::new("Recording mode", RecordingModeParam::Free),
+            recording_length_bars: IntParam::new(
+                "Recording length",
+                1,
+                IntRange::Linear { min: 1, max: 16 },
+            )
+            .with_unit(" bars"),
+            overdub_feedback: FloatParam::new(
+                "Overdub feedback",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            capture_length_bars: IntParam::new(
+                "Capture length",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_CAPTURE_BARS,
+                },
+            )
+            .with_unit(" bars"),
+            output_routing: EnumParam::new("Output routing", OutputRoutingParam::Off),
+            notes_per_output: IntParam::new(
+                "Notes per output",
+                4,
+                IntRange::Linear { min: 1, max: 16 },
+            ),
+            reverse_mode: EnumParam::new("Reverse mode", ReverseModeParam::Hold),
+            // velocities never go above 1.0, so the maximum disables it
+            reverse_velocity: FloatParam::new(
+                "Reverse velocity",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(Arc::new(|value| {
+                if value >= 1.0 {
+                    "Off".to_string()
+                } else {
+                    format!("{:.2}", value)
+                }
+            })),
+            max_voices: IntParam::new("Max voices", 16, IntRange::Linear { min: 1, max: 64 }),
+            voice_stealing: EnumParam::new("Voice stealing", VoiceStealingParam::Oldest),
+            retrigger: EnumParam::new("Retrigger", RetriggerParam::Layer),
+            playback_mode: EnumParam::new("Playback mode", PlaybackModeParam::Resample),
+            pitch: FloatParam::new(
+                "Pitch",
+                0.0,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" st"),
+            grain_size: FloatParam::new(
+                "Grain size",
+                50.0,
+                FloatRange::Skewed {
+                    min: 5.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms"),
+            loop_crossfade: FloatParam::new(
+                "Loop crossfade",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 100.0,
+                    factor: ATTACK_DECAY_SKEW_FACTOR,
+                },
+            )
+            .with_unit(" ms"),
+            onset_sensitivity: FloatParam::new(
+                "Onset sensitivity",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            ),
+            slice_count: EnumParam::new("Slice count", SliceCountParam::Sixteen),
+            custom_slice_count: IntParam::new(
+                "Custom slice count",
+                12,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_SLICES as i32,
+                },
+            ),
+            slice_length: EnumParam::new("Slice length", SliceLengthParam::SliceCount),
+            record_note: IntParam::new("Record note", 0, IntRange::Linear { min: 0, max: 127 })
+                .with_value_to_string(Arc::new(note_name)),
+            reverse_note: IntParam::new("Reverse note", 1, IntRange::Linear { min: 0, max: 127 })
+                .with_value_to_string(Arc::new(note_name)),
+            capture_note: IntParam::new("Capture note", 2, IntRange::Linear { min: 0, max: 127 })
+                .with_value_to_string(Arc::new(note_name)),
+            export_note: IntParam::new("Export note", 3, IntRange::Linear { min: 0, max: 127 })
+                .with_value_to_string(Arc::new(note_name)),
+            slice_base_note: IntParam::new(
+                "Slice base note",
+                12,
+                IntRange::Linear { min: 0, max: 127 },
+            )
+            .with_value_to_string(Arc::new(note_name)),
+            slot_base_note: IntParam::new(
+                "Slot base note",
+                48,
+                IntRange::Linear { min: 0, max: 127 },
+            )
+            .with_value_to_string(Arc::new(note_name)),
+            export_format: EnumParam::new("Export format", WavFormatParam::Float32),
+            export_dir: PersistedPath::default(),
+            import_path: PersistedPath::default(),
+            recorded_audio: PersistedAudio::default(),
+            stored_audio_limit: IntParam::new(
+                "Stored audio limit",
+                32,
+                IntRange::Linear { min: 0, max: 256 },
+            )
+            .with_unit(" MB"),
+            max_recording_length: IntParam::new(
+                "Max recording length",
+                30,
+                IntRange::Linear { min: 1, max: 600 },
+            )
+            .with_unit(" s"),
+        }
+    }
+}
+
+impl AudioSamplerParams {
+    pub fn note_param(&self, role: NoteRole) -> &IntParam {
+        match role {
+            NoteRole::Record => &self.record_note,
+            NoteRole::Reverse => &self.reverse_note,
+            NoteRole::Capture => &self.capture_note,
+            NoteRole::Export => &self.export_note,
+            NoteRole::SliceBase => &self.slice_base_note,
+            NoteRole::SlotBase => &self.slot_base_note,
+            NoteRole::ChromaticBase => &self.chromatic_base_note,
         }
     }
 }
@@ -430,8 +1065,30 @@ impl Default for AudioSampler {
             last_frame_recorded: 0,
             last_waveform_updated: 0,
             active_notes: [[0; 256]; 16],
+            held_notes: [[HeldNote::None; 256]; 16],
             reversing: false,
+            reverse_latched: false,
+            bend: 1.0,
+            scratch: Scratch::default(),
+            scratch_pos: 0,
             iteration: 0,
+            selected_slot: 0,
+            last_sample_slot_param: 1,
+            export_requested: Arc::new(AtomicBool::new(false)),
+            load_requested: Arc::new(AtomicBool::new(false)),
+            note_learn: Arc::new(NoteLearn::default()),
+            pending_load: Default::default(),
+            pending_spare: Default::default(),
+            spare_requested: false,
+            max_recording_samples: 0,
+            pending_slices: Default::default(),
+            slices_requested: false,
+            pending_restore: vec![],
+            persisted_data_version: 0,
+            inputs: vec![],
+            record_inputs: vec![],
+            commands: vec![],
+            speed_values: vec![],
         }
     }
 }
@@ -465,6 +1122,11 @@ impl AudioSampler {
         self.active_notes[note.channel as usize][note.note as usize] > 0
     }
 
+    fn hold_note(&mut self, note: &Note, held: HeldNote) {
+        self.set_note_active(note, true);
+        self.held_notes[note.channel as usize][note.note as usize] = held;
+    }
+
     fn set_note_active(&mut self, note: &Note, active: bool) {
         let current = &mut self.active_notes[note.channel as usize][note.note as usize];
         if active {
@@ -505,17 +1167,234 @@ impl AudioSampler {
         }
     }
 
-    fn sampler_params(&self, sample_id: usize, transport: &Transport) -> SamplerParams {
-        let params_speed = self.params.speed.smoothed.next();
+    fn slice_length(&self) -> TimeOrRatio {
+        if let Some(time) = self.params.slice_length.value().time_value() {
+            return TimeOrRatio::Time(time);
+        }
+        TimeOrRatio::Ratio(1.0 / self.note_map().slice_count as f32)
+    }
+
+    // before it is fitted to the notes, see `NoteMap::fit_slices`
+    fn slice_count_param(&self) -> usize {
+        match self.params.slice_count.value() {
+            SliceCountParam::Four => 4,
+            SliceCountParam::Eight => 8,
+            SliceCountParam::Sixteen => 16,
+            SliceCountParam::ThirtyTwo => 32,
+            SliceCountParam::SixtyFour => 64,
+            SliceCountParam::Custom => self.params.custom_slice_count.value() as usize,
+        }
+    }
+
+    fn select_slot(&mut self, slot: usize) {
+        if slot != self.selected_slot {
+            self.selected_slot = slot;
+            #[cfg(feature = "use_vizia")]
+            self.update_waveform();
+        }
+    }
+
+    // only swaps buffers, the slot's previous ones are released by a background task
+    fn apply_pending_load(&mut self) {
+        // background task may be holding the lock, try again on the next buffer
+        let loaded = match self.pending_load.try_lock() {
+            Some(mut pending) => pending.take(),
+            None => None,
+        };
+        if let Some(loaded) = loaded {
+            self.sampler.replace_slot(loaded.slot, loaded.data);
+            #[cfg(feature = "use_vizia")]
+            self.update_waveform();
+        }
+    }
+
+    // restores the persisted file, outside of the audio thread so it can load synchronously
+    // data is handed over on the first buffer as the sampler is reset after initialize
+    fn load_import_path(&mut self) {
+        let path = self.params.import_path.0.lock().clone();
+        if path.is_empty() {
+            return;
+        }
+        match audio_file::load(
+            std::path::Path::new(&path),
+            self.channel_count(),
+            self.sample_rate,
+        ) {
+            Ok(data) => {
+                *self.pending_load.lock() = Some(LoadedFile {
+                    slot: self.selected_slot,
+                    data: SharedSlot::new(
+                        data,
+                        self.sample_rate,
+                        self.max_recording_samples,
+                        self.params.onset_sensitivity.value(),
+                    ),
+                })
+            }
+            Err(err) => nih_error!("failed loading {}: {}", path, err),
+        }
+    }
+
+    // resampled here so that restoring on the audio thread only moves buffers. slots of the
+    // previous sampler survive re-initializing, e.g. on a sample rate change, unless the host
+    // has loaded a state since. they are published right away so a state saved before the
+    // first buffer still has them
+    fn prepare_persisted_audio(&mut self, previous: Vec<SlotSnapshot>) {
+        let channel_count = self.channel_count();
+        let sample_rate = self.sample_rate;
+        let slot_count = self.sampler.get_slot_count();
+        let persisted = &self.params.recorded_audio;
+        let state_loaded = persisted.state_loaded.swap(false, Ordering::Relaxed);
+        let snapshots = if state_loaded || previous.is_empty() {
+            persisted.loaded.lock().clone()
+        } else {
+            previous
+        };
+        self.pending_restore = snapshots
+            .into_iter()
+            .filter(|s| s.slot < slot_count)
+            .map(|s| {
+                let slot = s.slot;
+                let s = snapshot::prepare(s, channel_count, sample_rate);
+                (slot, SharedSlot::from_snapshot(s))
+            })
+            .collect();
+        let mut slots = persisted.slots.lock();
+        *slots = vec![SharedSlot::default(); slot_count];
+        for (slot, data) in &self.pending_restore {
+            slots[*slot] = data.clone();
+        }
+        persisted.max_bytes.store(
+            self.params.stored_audio_limit.value() as usize * 1024 * 1024,
+            Ordering::Relaxed,
+        );
+    }
+
+    // onsets of changed slots are detected by the background task, until then their slices
+    // follow the grid. the next take or capture goes into a spare the background task
+    // prepares, a copy of the selected slot so overdubs keep its contents. while the slot is
+    // being recorded into it can't be copied, an empty spare still lets the next take start
+    // right after this one
+    fn update_buffers(&mut self, context: &mut impl ProcessContext<Self>) {
+        // background task may be holding the locks, try again on the next buffer
+        if let Some(detected) = self.pending_slices.try_lock().and_then(|mut p| p.take()) {
+            self.sampler
+                .set_slices(detected.slot, detected.version, &detected.slices);
+            self.slices_requested = false;
+        }
+        // one slot at a time, a slot that changed again meanwhile is detected once more
+        let stale = self
+            .sampler
+            .stale_slices()
+            .filter(|_| !self.slices_requested);
+        if let Some((slot, source)) =
+            stale.and_then(|slot| Some((slot, self.sampler.share_slot(slot)?)))
+        {
+            context.execute_background(Task::DetectSlices {
+                slot,
+                source,
+                sensitivity: self.params.onset_sensitivity.value(),
+                pending: self.pending_slices.clone(),
+            });
+            self.slices_requested = true;
+        }
+        if let Some(spare) = self.pending_spare.try_lock().and_then(|mut p| p.take()) {
+            self.sampler.set_spare(spare);
+            self.spare_requested = false;
+        }
+        if !self.spare_requested && !self.sampler.spare_is_current(self.selected_slot) {
+            let source = self.sampler.share_slot(self.selected_slot);
+            if source.is_some() || !self.sampler.has_spare() {
+                context.execute_background(Task::PrepareSpare {
+                    slot: self.selected_slot,
+                    source,
+                    channel_count: self.channel_count(),
+                    capacity: self.max_recording_samples,
+                    pending: self.pending_spare.clone(),
+                });
+                self.spare_requested = true;
+            }
+        }
+        let mut retired = self.sampler.drain_retired().peekable();
+        while retired.peek().is_some() {
+            context.execute_background(Task::Release(
+                retired.by_ref().take(INLINE_CHANNELS).collect(),
+            ));
+        }
+    }
+
+    // publishes the slots for the plugin state whenever they change, they are only copied
+    // when the state is saved. replaced entries may hold the last handle on their buffers,
+    // so they are released by a background task
+    fn update_persisted_audio(&mut self, context: &mut impl ProcessContext<Self>) {
+        let persisted = &self.params.recorded_audio;
+        persisted.max_bytes.store(
+            self.params.stored_audio_limit.value() as usize * 1024 * 1024,
+            Ordering::Relaxed,
+        );
+        let version = self.sampler.get_data_version();
+        if version == self.persisted_data_version {
+            return;
+        }
+        // state may be being saved, try again on the next buffer
+        let Some(mut slots) = persisted.slots.try_lock() else {
+            return;
+        };
+        for (slot, entry) in slots.iter_mut().enumerate() {
+            // a take is published once it ends
+            let Some(data) = self.sampler.share_slot(slot) else {
+                continue;
+            };
+            if data.version != entry.version || data.slices != entry.slices {
+                let previous = std::mem::replace(entry, data);
+                if !previous.channels.is_empty() {
+                    context.execute_background(Task::Release(previous.channels));
+                }
+            }
+        }
+        self.persisted_data_version = version;
+    }
+
+    // the file is written by a background task reading the selected slot's buffers. a slot
+    // that is being recorded into is exported once the take ends
+    fn export_wav(&self, context: &mut impl ProcessContext<Self>) {
+        if let Some(data) = self.sampler.share_slot(self.selected_slot) {
+            self.export_requested.store(false, Ordering::Relaxed);
+            context.execute_background(Task::ExportWav {
+                dir: self.params.export_dir.clone(),
+                data,
+                format: self.params.export_format.value().into(),
+            });
+        }
+    }
+
+    // slot can be selected by both the param and notes, whichever changed last wins
+    fn update_selected_slot_from_param(&mut self) {
+        let value = self.params.sample_slot.value();
+        if value != self.last_sample_slot_param {
+            self.last_sample_slot_param = value;
+            self.select_slot((value - 1) as usize);
+        }
+    }
+
+    // speed_values must already hold the smoothed speed for this block
+    fn sampler_params(&self, block_len: usize, transport: &Transport) -> SamplerParams {
+        let steps = block_len as u32;
+        let params_speed = self.speed_values[0];
         let params_passthru = self.params.auto_passthru.value();
-        let attack_millis = self.params.attack.smoothed.next();
+        let attack_millis = self.params.attack.smoothed.next_step(steps);
         let attack_samples = (attack_millis * self.sample_rate / 1000.0) as usize;
-        let decay_millis = self.params.decay.smoothed.next();
+        let hold_millis = self.params.hold.smoothed.next_step(steps);
+        let hold_samples = (hold_millis * self.sample_rate / 1000.0) as usize;
+        let decay_millis = self.params.decay.smoothed.next_step(steps);
         let decay_samples = (decay_millis * self.sample_rate / 1000.0) as usize;
+        let release_millis = self.params.release.smoothed.next_step(steps);
+        let release_samples = (release_millis * self.sample_rate / 1000.0) as usize;
 
         let transport = audio_sampler_lib::common_types::Transport {
             sample_rate: self.sample_rate,
             tempo: transport.tempo.unwrap() as f32,
+            // start of the buffer, the sampler advances it for commands within the block
             pos_samples: transport.pos_samples().unwrap() as f32,
             time_sig_numerator: transport.time_sig_numerator.unwrap() as u32,
             time_sig_denominator: transport.time_sig_denominator.unwrap() as u32,
@@ -528,22 +1407,124 @@ impl AudioSampler {
             loop_length: self.loop_length(),
             // start_offset_percent: self.params.start_offset.value(),
             start_offset_percent: 0.0,
+            hold_samples,
             decay_samples,
+            sustain_level: self.params.sustain.value(),
+            release_samples,
+            envelope_curve: self.params.envelope_curve.value().into(),
             speed: params_speed,
-            fixed_size_samples: TimeValue::bars(1.0)
+            fixed_size_samples: TimeValue::bars(self.params.recording_length_bars.value() as f32)
                 .as_samples(&transport)
                 .to_usize()
                 .expect("failed converting value for fixed_size_samples from f32 to usize"),
             transport,
-            sample_id,
+            sample_id: self.selected_slot,
             reverse_speed: if self.reversing { -1.0 } else { 1.0 },
             note_off_behavior: self.params.note_off_behavior.value().into(),
+            interpolation: self.params.interpolation.value().into(),
+            recording_mode: self.params.recording_mode.value().into(),
+            overdub_feedback: self.params.overdub_feedback.value(),
+            capture_length: TimeValue::bars(self.params.capture_length_bars.value() as f32),
+            max_voices: self.params.max_voices.value() as usize,
+            voice_stealing: self.params.voice_stealing.value().into(),
+            retrigger: self.params.retrigger.value().into(),
+            playback_mode: self.playback_mode(),
+            pitch: self.pitch_ratio(),
+            grain_samples: (self.params.grain_size.value() * self.sample_rate / 1000.0) as usize,
+            loop_crossfade_samples: (self.params.loop_crossfade.value() * self.sample_rate / 1000.0)
+                as usize,
+            onset_sensitivity: self.params.onset_sensitivity.value(),
+            slice_length: self.slice_length(),
+            output_routing: self.params.output_routing.value().into(),
+            output_count: self.audio_io_layout.aux_output_ports.len(),
+            // note ranges start at the first slice note
+            output_base_note: self.params.slice_base_note.value() as u8,
+            notes_per_output: self.params.notes_per_output.value() as usize,
         };
         params
     }
 
-    fn update_peak_meter(&mut self, frame: &mut [&mut f32]) {
-        let amplitude = (frame.iter().fold(0.0, |z, x| z + **x) / frame.len() as f32).abs();
+    // smoothed speed changes are sent as commands, interleaved with the note events
+    fn push_speed_updates(&mut self, next_update: &mut usize, until: usize) {
+        while *next_update <= until {
+            self.push_speed_update(*next_update, until + 1);
+            *next_update += SPEED_UPDATE_SAMPLES;
+        }
+    }
+
+    // offsets must not decrease within a buffer as the scratch control only moves forward
+    fn push_speed_update(&mut self, offset: usize, num_samples: usize) {
+        self.advance_scratch(offset);
+        let (speed, pitch) = self.bend_and_scratch();
+        let speed = self.speed_values[offset.min(num_samples.saturating_sub(1))] * speed;
+        self.commands.push((offset, Command::SetSpeed { speed }));
+        if self.playback_mode() == PlaybackMode::Granular {
+            let pitch = self.pitch_ratio() * pitch;
+            self.commands.push((offset, Command::SetPitch { pitch }));
+        }
+    }
+
+    // speed and pitch ratios from pitch bend and scratching. Pitch bend acts like the speed
+    // param in Resample mode and like the pitch param in Granular mode. Scratching moves the
+    // playhead, so it changes speed in both.
+    fn bend_and_scratch(&self) -> (f32, f32) {
+        match self.playback_mode() {
+            PlaybackMode::Resample => (self.bend * self.scratch.speed(), 1.0),
+            PlaybackMode::Granular => (self.scratch.speed(), self.bend),
+        }
+    }
+
+    fn pitch_ratio(&self) -> f32 {
+        2.0_f32.powf(self.params.pitch.value() / 12.0)
+    }
+
+    fn playback_mode(&self) -> PlaybackMode {
+        self.params.playback_mode.value().into()
+    }
+
+    // velocity and Latch reverse mode can start any voice backwards
+    fn starts_reversed(&self, velocity: f32) -> bool {
+        velocity > self.params.reverse_velocity.value()
+            || (self.params.reverse_mode.value() == ReverseModeParam::Latch && self.reverse_latched)
+    }
+
+    fn note_map(&self) -> NoteMap {
+        NoteMap {
+            record: self.params.record_note.value() as u8,
+            reverse: self.params.reverse_note.value() as u8,
+            capture: self.params.capture_note.value() as u8,
+            export: self.params.export_note.value() as u8,
+            slice_base: self.params.slice_base_note.value() as u8,
+            slice_count: self.slice_count_param() as u8,
+            slot_base: self.params.slot_base_note.value() as u8,
+            slot_count: DEFAULT_SLOT_COUNT as u8,
+            chromatic_base: self.params.chromatic_base_note.value() as u8,
+        }
+        .fit_slices()
+    }
+
+    fn pitch_tracking(&self) -> PitchTracking {
+        self.params.pitch_tracking.value().into()
+    }
+
+    // pitch of a note in the chromatic range relative to the root note
+    fn chromatic_semitones(&self, note_map: &NoteMap, note: u8) -> Option<f32> {
+        if note_map.action(note) != Some(NoteAction::Chromatic)
+            || self.pitch_tracking() != PitchTracking::Exponential
+        {
+            return None;
+        }
+        Some((note as i32 - self.params.root_note.value()) as f32)
+    }
+
+    fn advance_scratch(&mut self, offset: usize) {
+        let inertia_samples = self.params.scratch_inertia.value() * self.sample_rate / 1000.0;
+        self.scratch
+            .advance(offset.saturating_sub(self.scratch_pos), inertia_samples);
+        self.scratch_pos = self.scratch_pos.max(offset);
+    }
+
+    fn update_peak_meter(&mut self, amplitude: f32) {
         let current_peak_meter = self.peak_meter.load(std::sync::atomic::Ordering::Relaxed);
         let new_peak_meter = if amplitude > current_peak_meter {
             amplitude
@@ -559,7 +1540,7 @@ impl AudioSampler {
     fn update_waveform(&mut self) {
         self.waveform_summary = Arc::new(VersionedWaveformSummary {
             version: self.waveform_summary.version + 1,
-            waveform_summary: self.sampler.get_waveform_summary(940),
+            waveform_summary: self.sampler.get_waveform_summary(self.selected_slot, 940),
         });
     }
 }
@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicU16, Ordering};
+
+// Notes that trigger the plugin's actions. The notes are params, read once per buffer.
+// Learning happens on the audio thread, which can't set params, so a learned note waits in
+// NoteLearn until the editor sets the param to it.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NoteRole {
+    Record,
+    Reverse,
+    Capture,
+    Export,
+    SliceBase,
+    SlotBase,
+    ChromaticBase,
+}
+
+impl NoteRole {
+    pub const ALL: [NoteRole; 7] = [
+        NoteRole::Record,
+        NoteRole::Reverse,
+        NoteRole::Capture,
+        NoteRole::Export,
+        NoteRole::SliceBase,
+        NoteRole::SlotBase,
+        NoteRole::ChromaticBase,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoteRole::Record => "Record note",
+            NoteRole::Reverse => "Reverse note",
+            NoteRole::Capture => "Capture note",
+            NoteRole::Export => "Export note",
+            NoteRole::SliceBase => "Slice base note",
+            NoteRole::SlotBase => "Slot base note",
+            NoteRole::ChromaticBase => "Chromatic base note",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NoteAction {
+    Record,
+    Reverse,
+    Capture,
+    Export,
+    // the slice notes are followed by the same slices again, played backwards
+    Slice { slice: usize, reverse: bool },
+    SelectSlot { slot: usize },
+    Chromatic,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NoteMap {
+    pub record: u8,
+    pub reverse: u8,
+    pub capture: u8,
+    pub export: u8,
+    pub slice_base: u8,
+    pub slice_count: u8,
+    pub slot_base: u8,
+    pub slot_count: u8,
+    // every note from here up
+    pub chromatic_base: u8,
+}
+
+impl NoteMap {
+    /// Caps the slice count so the slices and the backwards slices after them end before the
+    /// next range above the slice base note, or the last note. Also the count of the slice
+    /// grid, so every slice has its notes. At least one slice is kept.
+    pub fn fit_slices(mut self) -> Self {
+        let base = self.slice_base as usize;
+        let end = [self.slot_base, self.chromatic_base]
+            .into_iter()
+            .map(usize::from)
+            .filter(|&note| note > base)
+            .min()
+            .unwrap_or(128);
+        let room = ((end - base) / 2).max(1);
+        self.slice_count = self.slice_count.min(room as u8);
+        self
+    }
+
+    // where notes overlap, single notes take precedence over the ranges, and slot notes over
+    // slices and chromatic notes, slices over chromatic notes
+    pub fn action(&self, note: u8) -> Option<NoteAction> {
+        if note == self.record {
+            return Some(NoteAction::Record);
+        }
+        if note == self.reverse {
+            return Some(NoteAction::Reverse);
+        }
+        if note == self.capture {
+            return Some(NoteAction::Capture);
+        }
+        if note == self.export {
+            return Some(NoteAction::Export);
+        }
+        if let Some(slot) = note
+            .checked_sub(self.slot_base)
+            .filter(|&slot| slot < self.slot_count)
+        {
+            return Some(NoteAction::SelectSlot {
+                slot: slot as usize,
+            });
+        }
+        let count = self.slice_count.max(1) as usize;
+        if let Some(offset) = (note as usize)
+            .checked_sub(self.slice_base as usize)
+            .filter(|&offset| offset < 2 * count)
+        {
+            return Some(NoteAction::Slice {
+                slice: offset % count,
+                reverse: offset >= count,
+            });
+        }
+        (note >= self.chromatic_base).then_some(NoteAction::Chromatic)
+    }
+}
+
+// role in the high byte, the learned note in the low one
+const NOT_LEARNING: u16 = u16::MAX;
+const WAITING: u8 = u8::MAX;
+
+pub struct NoteLearn {
+    state: AtomicU16,
+}
+
+impl Default for NoteLearn {
+    fn default() -> Self {
+        Self {
+            state: AtomicU16::new(NOT_LEARNING),
+        }
+    }
+}
+
+impl NoteLearn {
+    /// The next note-on is assigned to `role` instead of being played
+    pub fn learn(&self, role: NoteRole) {
+        self.state
+            .store(((role as u16) << 8) | WAITING as u16, Ordering::Relaxed);
+    }
+
+    /// Returns true when MIDI learn took the note
+    pub fn try_learn(&self, note: u8) -> bool {
+        let state = self.state.load(Ordering::Relaxed);
+        let role = (state >> 8) as usize;
+        if role >= NoteRole::ALL.len() || state as u8 != WAITING {
+            return false;
+        }
+        self.state
+            .compare_exchange(
+                state,
+                (state & 0xff00) | note as u16,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    }
+
+    /// Note learned for a role, for the editor to set its param. Returns it only once.
+    pub fn take_learned(&self) -> Option<(NoteRole, u8)> {
+        let state = self.state.load(Ordering::Relaxed);
+        let role = *NoteRole::ALL.get((state >> 8) as usize)?;
+        let note = state as u8;
+        if note == WAITING {
+            return None;
+        }
+        self.state
+            .compare_exchange(state, NOT_LEARNING, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()
+            .map(|_| (role, note))
+    }
+}
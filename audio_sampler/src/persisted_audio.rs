@@ -0,0 +1,98 @@
+use audio_sampler_lib::shared::SharedSlot;
+use audio_sampler_lib::snapshot;
+use audio_sampler_lib::snapshot::SlotSnapshot;
+use nih_plug::nih_error;
+use nih_plug::params::persist::PersistentField;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Recorded slots stored in the plugin state as base64 encoded snapshots. The audio thread
+// publishes handles on the slot buffers whenever they change, they are only copied when the
+// state is saved. A loaded state is kept as snapshots until the plugin restores them.
+#[derive(Clone, Default)]
+pub struct PersistedAudio {
+    // indexed by slot
+    pub slots: Arc<parking_lot::Mutex<Vec<SharedSlot>>>,
+    pub loaded: Arc<parking_lot::Mutex<Vec<SlotSnapshot>>>,
+    // set when the host loads a state
+    pub state_loaded: Arc<AtomicBool>,
+    // the stored audio limit
+    pub max_bytes: Arc<AtomicUsize>,
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let mut table = [u8::MAX; 256];
+    for (i, &c) in BASE64.iter().enumerate() {
+        table[c as usize] = i as u8;
+    }
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in s.bytes().filter(|&c| c != b'=') {
+        let x = table[c as usize];
+        if x == u8::MAX {
+            return None;
+        }
+        let x = x as u32;
+        n = n << 6 | x;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+impl<'a> PersistentField<'a, String> for PersistedAudio {
+    fn set(&self, new_value: String) {
+        let snapshots = base64_decode(&new_value)
+            .ok_or_else(|| "invalid base64".to_string())
+            .and_then(|bytes| snapshot::decode(&bytes).map_err(|err| err.to_string()));
+        *self.loaded.lock() = match snapshots {
+            Ok(snapshots) => snapshots,
+            Err(err) => {
+                nih_error!("failed restoring recorded audio: {}", err);
+                vec![]
+            }
+        };
+        self.state_loaded.store(true, Ordering::Relaxed);
+    }
+
+    fn map<F, R>(&self, f: F) -> R
+    where
+        F: Fn(&String) -> R,
+    {
+        // a loaded state that hasn't been restored yet is saved as it is
+        let snapshots = if self.state_loaded.load(Ordering::Relaxed) {
+            self.loaded.lock().clone()
+        } else {
+            // copied outside the lock so the audio thread can keep publishing
+            let slots = self.slots.lock().clone();
+            snapshot::snapshot_slots(&slots, self.max_bytes.load(Ordering::Relaxed))
+        };
+        f(&base64_encode(&snapshot::encode(&snapshots)))
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use audio_sampler_lib::audio_file;
+use audio_sampler_lib::common_types::WavFormat;
+use audio_sampler_lib::shared::{Buffers, SharedSlot, Slices, Spare};
+use audio_sampler_lib::wav;
+use nih_plug::{nih_error, nih_log};
+
+use crate::persisted_path::PersistedPath;
+
+// decoded file waiting to be picked up by the audio thread, ready to be swapped in
+pub struct LoadedFile {
+    pub slot: usize,
+    pub data: SharedSlot,
+}
+
+pub type PendingLoad = Arc<parking_lot::Mutex<Option<LoadedFile>>>;
+
+pub type PendingSpare = Arc<parking_lot::Mutex<Option<Spare>>>;
+
+// onsets found in one version of a slot
+pub struct DetectedSlices {
+    pub slot: usize,
+    pub version: usize,
+    pub slices: Slices,
+}
+
+pub type PendingSlices = Arc<parking_lot::Mutex<Option<DetectedSlices>>>;
+
+// work that must not run on the audio thread
+pub enum Task {
+    LoadFile {
+        path: PersistedPath,
+        slot: usize,
+        channel_count: usize,
+        sample_rate: f32,
+        max_len: usize,
+        sensitivity: f32,
+        pending: PendingLoad,
+    },
+    ExportWav {
+        dir: PersistedPath,
+        data: SharedSlot,
+        format: WavFormat,
+    },
+    // copies the slot into buffers with room for a whole take, without a source they start
+    // out empty
+    PrepareSpare {
+        slot: usize,
+        source: Option<SharedSlot>,
+        channel_count: usize,
+        capacity: usize,
+        pending: PendingSpare,
+    },
+    DetectSlices {
+        slot: usize,
+        source: SharedSlot,
+        sensitivity: f32,
+        pending: PendingSlices,
+    },
+    // buffers the sampler let go of, dropping them here keeps freeing off the audio thread
+    Release(Buffers),
+}
+
+// UTC date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let y = yoe + era * 400 + (if m <= 2 { 1 } else { 0 });
+    (y, m, d)
+}
+
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (y, m, d) = civil_from_days(secs.div_euclid(86400));
+    let t = secs.rem_euclid(86400);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        y,
+        m,
+        d,
+        t / 3600,
+        t / 60 % 60,
+        t % 60,
+        now.subsec_millis()
+    )
+}
+
+// empty directory falls back to the system temporary directory
+fn export_path(dir: &str) -> PathBuf {
+    let dir = if dir.is_empty() {
+        std::env::temp_dir()
+    } else {
+        PathBuf::from(dir)
+    };
+    dir.join(format!("audio-sampler-{}.wav", timestamp()))
+}
+
+impl Task {
+    pub fn run(self) {
+        match self {
+            Task::LoadFile {
+                path,
+                slot,
+                channel_count,
+                sample_rate,
+                max_len,
+                sensitivity,
+                pending,
+            } => {
+                let path = path.0.lock().clone();
+                match audio_file::load(Path::new(&path), channel_count, sample_rate) {
+                    Ok(data) => {
+                        nih_log!("loaded {}", path);
+                        let data = SharedSlot::new(data, sample_rate, max_len, sensitivity);
+                        *pending.lock() = Some(LoadedFile { slot, data });
+                    }
+                    Err(err) => nih_error!("failed loading {}: {}", path, err),
+                }
+            }
+            Task::ExportWav { dir, data, format } => {
+                let path = export_path(&dir.0.lock());
+                let channels: Vec<&[f32]> = data.channels.iter().map(|x| &x[..]).collect();
+                let sample_rate = data.sample_rate.round() as u32;
+                match wav::export(&path, &channels, sample_rate, format) {
+                    Ok(()) => nih_log!("exported {}", path.display()),
+                    Err(err) => nih_error!("failed exporting {}: {}", path.display(), err),
+                }
+            }
+            Task::PrepareSpare {
+                slot,
+                source,
+                channel_count,
+                capacity,
+                pending,
+            } => {
+                let spare = match source {
+                    Some(source) => Spare::new(slot, &source, channel_count, capacity),
+                    None => Spare::empty(channel_count, capacity),
+                };
+                *pending.lock() = Some(spare);
+            }
+            Task::DetectSlices {
+                slot,
+                source,
+                sensitivity,
+                pending,
+            } => {
+                let slices = source.detect_slices(sensitivity);
+                *pending.lock() = Some(DetectedSlices {
+                    slot,
+                    version: source.version,
+                    slices,
+                });
+            }
+            Task::Release(buffers) => drop(buffers),
+        }
+    }
+}
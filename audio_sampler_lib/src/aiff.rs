@@ -0,0 +1,145 @@
+use std::io;
+
+use crate::audio_file::{
+    decode_samples, invalid_data, is_valid_sample_rate, AudioData, SampleEncoding,
+};
+
+fn u16_be(bytes: &[u8], at: usize) -> io::Result<u16> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid_data("truncated AIFF file"))
+}
+
+fn u32_be(bytes: &[u8], at: usize) -> io::Result<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid_data("truncated AIFF file"))
+}
+
+// sample rate is stored as an 80 bit IEEE 754 extended float
+fn extended_to_f64(bytes: &[u8]) -> f64 {
+    let exponent = (((bytes[0] as i32) & 0x7f) << 8) | bytes[1] as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    let sign = if bytes[0] & 0x80 != 0 { -1.0 } else { 1.0 };
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    sign * mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+}
+
+/// Decodes AIFF and uncompressed AIFF-C files
+pub fn decode(bytes: &[u8]) -> io::Result<AudioData> {
+    let is_aifc = match bytes.get(8..12) {
+        Some(b"AIFF") => false,
+        Some(b"AIFC") => true,
+        _ => return Err(invalid_data("not an AIFF file")),
+    };
+    if bytes.get(0..4) != Some(b"FORM") {
+        return Err(invalid_data("not an AIFF file"));
+    }
+    // channels, bits per sample, sample rate and encoding
+    let mut comm: Option<(usize, usize, f32, SampleEncoding)> = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let size = u32_be(bytes, at + 4)? as usize;
+        let body = at + 8;
+        match id {
+            b"COMM" => {
+                let rate = bytes
+                    .get(body + 8..body + 18)
+                    .ok_or_else(|| invalid_data("truncated AIFF file"))?;
+                let compression = if is_aifc {
+                    bytes.get(body + 18..body + 22)
+                } else {
+                    Some(&b"NONE"[..])
+                };
+                let encoding = match compression {
+                    Some(b"NONE") | Some(b"twos") => SampleEncoding::Pcm { big_endian: true },
+                    Some(b"sowt") => SampleEncoding::Pcm { big_endian: false },
+                    Some(b"fl32") | Some(b"FL32") | Some(b"fl64") | Some(b"FL64") => {
+                        SampleEncoding::Float { big_endian: true }
+                    }
+                    _ => return Err(invalid_data("unsupported AIFF compression")),
+                };
+                let bits = match compression {
+                    Some(b"fl32") | Some(b"FL32") => 32,
+                    Some(b"fl64") | Some(b"FL64") => 64,
+                    _ => u16_be(bytes, body + 6)? as usize,
+                };
+                comm = Some((
+                    u16_be(bytes, body)? as usize,
+                    bits,
+                    extended_to_f64(rate) as f32,
+                    encoding,
+                ));
+            }
+            b"SSND" => {
+                let (channel_count, bits, sample_rate, encoding) =
+                    comm.ok_or_else(|| invalid_data("AIFF sound data before COMM chunk"))?;
+                if !is_valid_sample_rate(sample_rate) {
+                    return Err(invalid_data("invalid AIFF sample rate"));
+                }
+                let start = body + 8 + u32_be(bytes, body)? as usize;
+                let end = (body + size).min(bytes.len());
+                if start > end {
+                    return Err(invalid_data("truncated AIFF file"));
+                }
+                return Ok(AudioData {
+                    channels: decode_samples(&bytes[start..end], channel_count, bits, encoding)?,
+                    sample_rate,
+                });
+            }
+            _ => (),
+        }
+        // chunks are word aligned
+        at = body + size + size % 2;
+    }
+    Err(invalid_data("AIFF file has no sound data"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn aiff_bytes(rate: [u8; 10]) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(b"FORM");
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        bytes.extend_from_slice(b"AIFF");
+        bytes.extend_from_slice(b"COMM");
+        bytes.extend_from_slice(&18u32.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&2u32.to_be_bytes());
+        bytes.extend_from_slice(&16u16.to_be_bytes());
+        bytes.extend_from_slice(&rate);
+        bytes.extend_from_slice(b"SSND");
+        bytes.extend_from_slice(&12u32.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        bytes.extend_from_slice(&[0x40, 0x00, 0xc0, 0x00]);
+        bytes
+    }
+
+    #[test]
+    fn test_decode_aiff() {
+        // 44100 as 80 bit extended
+        let audio = decode(&aiff_bytes([0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(audio.sample_rate, 44100.0);
+        assert_eq!(audio.channels, vec![vec![0.5, -0.5]]);
+    }
+
+    #[test]
+    fn test_decode_aiff_invalid_rate() {
+        // next to nothing and 1e9
+        for rate in [
+            [0x00, 0x01, 0x80, 0, 0, 0, 0, 0, 0, 0],
+            [0x40, 0x1c, 0xee, 0x6b, 0x28, 0, 0, 0, 0, 0],
+        ] {
+            let err = decode(&aiff_bytes(rate)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+use crate::{aiff, wav};
+
+// taps on each side of the read position when resampling, widened when downsampling
+const RESAMPLE_HALF_WIDTH: f32 = 16.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioData {
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: f32,
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// rates read from files and snapshots are only used once they pass this, resampling from
+// or to anything outside would size buffers by an absurd ratio
+const MIN_SAMPLE_RATE: f32 = 1000.0;
+const MAX_SAMPLE_RATE: f32 = 1_000_000.0;
+
+pub(crate) fn is_valid_sample_rate(rate: f32) -> bool {
+    (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&rate)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SampleEncoding {
+    // 8 bit WAV samples are unsigned, AIFF ones are signed
+    UnsignedPcm8,
+    Pcm { big_endian: bool },
+    Float { big_endian: bool },
+}
+
+/// Decodes interleaved frames into one buffer per channel
+pub(crate) fn decode_samples(
+    bytes: &[u8],
+    channel_count: usize,
+    bits: usize,
+    encoding: SampleEncoding,
+) -> io::Result<Vec<Vec<f32>>> {
+    let bytes_per_sample = bits.div_ceil(8);
+    if channel_count == 0 || bytes_per_sample == 0 || bytes_per_sample > 8 {
+        return Err(invalid_data("unsupported sample format"));
+    }
+    let frames = bytes.len() / (channel_count * bytes_per_sample);
+    let mut channels = vec![Vec::with_capacity(frames); channel_count];
+    for (i, sample) in bytes
+        .chunks_exact(bytes_per_sample)
+        .take(frames * channel_count)
+        .enumerate()
+    {
+        let mut le = [0u8; 8];
+        le[..bytes_per_sample].copy_from_slice(sample);
+        let big_endian = match encoding {
+            SampleEncoding::Pcm { big_endian } | SampleEncoding::Float { big_endian } => big_endian,
+            SampleEncoding::UnsignedPcm8 => false,
+        };
+        if big_endian {
+            le[..bytes_per_sample].reverse();
+        }
+        let value = match (encoding, bytes_per_sample) {
+            (SampleEncoding::UnsignedPcm8, 1) => (le[0] as f32 - 128.0) / 128.0,
+            (SampleEncoding::Float { .. }, 4) => f32::from_le_bytes([le[0], le[1], le[2], le[3]]),
+            (SampleEncoding::Float { .. }, 8) => f64::from_le_bytes(le) as f32,
+            (SampleEncoding::Pcm { .. }, n) if n <= 4 => {
+                // shift into the top of an i32 to sign extend, samples are left justified
+                let x = i32::from_le_bytes([le[0], le[1], le[2], le[3]]) << (32 - n * 8);
+                x as f32 / 2147483648.0
+            }
+            _ => return Err(invalid_data("unsupported sample format")),
+        };
+        channels[i % channel_count].push(value);
+    }
+    Ok(channels)
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<AudioData> {
+    match bytes.get(0..4) {
+        Some(b"RIFF") => wav::decode(bytes),
+        Some(b"FORM") => aiff::decode(bytes),
+        _ => Err(invalid_data("not a WAV or AIFF file")),
+    }
+}
+
+/// Mono is copied to every channel, several channels mixed down to mono are averaged,
+/// otherwise channels are taken in order, repeating from the first one if there are
+/// fewer than needed.
+pub fn map_channels(channels: Vec<Vec<f32>>, channel_count: usize) -> Vec<Vec<f32>> {
+    if channels.is_empty() || channels.len() == channel_count {
+        return channels;
+    }
+    if channel_count == 1 {
+        let n = channels.len() as f32;
+        let len = channels.iter().map(|data| data.len()).max().unwrap_or(0);
+        let mixed = (0..len)
+            .map(|i| channels.iter().filter_map(|data| data.get(i)).sum::<f32>() / n)
+            .collect();
+        return vec![mixed];
+    }
+    (0..channel_count)
+        .map(|i| channels[i % channels.len()].clone())
+        .collect()
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Band limited resampling with a Hann windowed sinc. Data is returned as it is when either
+/// rate is not a positive number.
+pub fn resample(data: &[f32], from_rate: f32, to_rate: f32) -> Vec<f32> {
+    if from_rate == to_rate
+        || data.is_empty()
+        || !is_valid_sample_rate(from_rate)
+        || !is_valid_sample_rate(to_rate)
+    {
+        return data.to_vec();
+    }
+    let ratio = to_rate as f64 / from_rate as f64;
+    let out_len = (data.len() as f64 * ratio).round() as usize;
+    // lower cutoff when downsampling to avoid aliasing
+    let cutoff = (ratio as f32).min(1.0);
+    let half_width = RESAMPLE_HALF_WIDTH / cutoff;
+    (0..out_len)
+        .map(|i| {
+            let x = (i as f64 / ratio) as f32;
+            let first = (x - half_width).ceil().max(0.0) as usize;
+            let last = ((x + half_width).floor() as usize).min(data.len() - 1);
+            (first..=last)
+                .map(|k| {
+                    let t = x - k as f32;
+                    let window = 0.5 + 0.5 * (PI * t / half_width).cos();
+                    data[k] * cutoff * sinc(cutoff * t) * window
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Reads a WAV or AIFF file, converted to `channel_count` channels at `sample_rate`
+pub fn load(path: &Path, channel_count: usize, sample_rate: f32) -> io::Result<Vec<Vec<f32>>> {
+    let audio = decode(&std::fs::read(path)?)?;
+    let channels = map_channels(audio.channels, channel_count);
+    Ok(channels
+        .iter()
+        .map(|data| resample(data, audio.sample_rate, sample_rate))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_samples() {
+        let bytes = [0x00, 0x80, 0xff, 0x7f];
+        let le = decode_samples(&bytes, 2, 16, SampleEncoding::Pcm { big_endian: false }).unwrap();
+        assert_eq!(le, vec![vec![-1.0], vec![32767.0 / 32768.0]]);
+        let be = decode_samples(&bytes, 1, 16, SampleEncoding::Pcm { big_endian: true }).unwrap();
+        assert_eq!(be, vec![vec![128.0 / 32768.0, -129.0 / 32768.0]]);
+        let u8 = decode_samples(&[0, 128, 255], 1, 8, SampleEncoding::UnsignedPcm8).unwrap();
+        assert_eq!(u8, vec![vec![-1.0, 0.0, 127.0 / 128.0]]);
+    }
+
+    #[test]
+    fn test_map_channels() {
+        assert_eq!(
+            map_channels(vec![vec![1.0, 2.0]], 2),
+            vec![vec![1.0, 2.0], vec![1.0, 2.0]]
+        );
+        assert_eq!(
+            map_channels(vec![vec![1.0, 2.0], vec![3.0, 4.0]], 1),
+            vec![vec![2.0, 3.0]]
+        );
+    }
+
+    #[test]
+    fn test_resample_sine() {
+        let sine = |rate: f32, n: usize| -> Vec<f32> {
+            (0..n)
+                .map(|i| (2.0 * PI * 1000.0 * i as f32 / rate).sin())
+                .collect()
+        };
+        for (from, to) in [(48000.0, 44100.0), (22050.0, 44100.0)] {
+            let out = resample(&sine(from, 4800), from, to);
+            let expected = sine(to, out.len());
+            assert_eq!(out.len(), (4800.0 * to / from) as usize);
+            // edges lack neighbours on one side
+            for i in 100..out.len() - 100 {
+                assert!(
+                    (out[i] - expected[i]).abs() < 2e-3,
+                    "{} {} {}",
+                    i,
+                    out[i],
+                    expected[i]
+                );
+            }
+        }
+
+        // invalid rates leave the data as it is instead of sizing the output from them
+        for (from, to) in [(0.0, 44100.0), (44100.0, -1.0), (f32::NAN, 44100.0)] {
+            assert_eq!(resample(&[1.0, 2.0], from, to), vec![1.0, 2.0]);
+        }
+    }
+}
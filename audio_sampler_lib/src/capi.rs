@@ -1,6 +1,11 @@
 mod sampler1 {
-    use crate::common_types::{EnumIndex, InitParams, NoteOffBehaviour, Params};
+    use crate::common_types::{
+        Command, EnumIndex, EnvelopeCurve, InitParams, Interpolation, NoteOffBehaviour,
+        OutputRouting, Params, PitchTracking, PlaybackMode, RecordingMode, Retrigger,
+        VoiceStealing, WavFormat, MAX_AUX_OUTPUTS,
+    };
     use crate::sampler::{LoopMode, Sampler};
+    use crate::shared::INLINE_CHANNELS;
     use core::slice;
     use smallvec::SmallVec;
 
@@ -24,6 +29,105 @@ mod sampler1 {
         NoteOffBehaviour::from_f32(x)
     }
 
+    #[no_mangle]
+    pub extern "C" fn interpolation_to_f32(i: Interpolation) -> f32 {
+        i.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn interpolation_from_f32(x: f32) -> Interpolation {
+        Interpolation::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn recording_mode_to_f32(rm: RecordingMode) -> f32 {
+        rm.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn recording_mode_from_f32(x: f32) -> RecordingMode {
+        RecordingMode::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn envelope_curve_to_f32(ec: EnvelopeCurve) -> f32 {
+        ec.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn envelope_curve_from_f32(x: f32) -> EnvelopeCurve {
+        EnvelopeCurve::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn voice_stealing_to_f32(vs: VoiceStealing) -> f32 {
+        vs.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn voice_stealing_from_f32(x: f32) -> VoiceStealing {
+        VoiceStealing::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn retrigger_to_f32(r: Retrigger) -> f32 {
+        r.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn retrigger_from_f32(x: f32) -> Retrigger {
+        Retrigger::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn playback_mode_to_f32(pm: PlaybackMode) -> f32 {
+        pm.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn playback_mode_from_f32(x: f32) -> PlaybackMode {
+        PlaybackMode::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn output_routing_to_f32(or: OutputRouting) -> f32 {
+        or.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn output_routing_from_f32(x: f32) -> OutputRouting {
+        OutputRouting::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn pitch_tracking_to_f32(pt: PitchTracking) -> f32 {
+        pt.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn pitch_tracking_from_f32(x: f32) -> PitchTracking {
+        PitchTracking::from_f32(x)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn pitch_tracking_speed(
+        pt: PitchTracking,
+        semitones: f32,
+        multiplier: f32,
+    ) -> f32 {
+        pt.speed(semitones, multiplier)
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wav_format_to_f32(wf: WavFormat) -> f32 {
+        wf.to_f32()
+    }
+
+    #[no_mangle]
+    pub extern "C" fn wav_format_from_f32(x: f32) -> WavFormat {
+        WavFormat::from_f32(x)
+    }
+
     #[no_mangle]
     pub extern "C" fn sampler_params_default() -> Params {
         Params::default()
@@ -46,6 +150,37 @@ mod sampler1 {
         sampler.reset()
     }
 
+    /// Drops retired buffers and prepares a spare for the selected slot, call it from
+    /// outside the audio thread or in between blocks
+    #[no_mangle]
+    pub unsafe extern "C" fn sampler_update_buffers(sampler: &mut Sampler, params: &Params) {
+        sampler.update_buffers(params)
+    }
+
+    /// Takes and captures dropped for want of a spare, see `sampler_update_buffers`
+    #[no_mangle]
+    pub unsafe extern "C" fn sampler_dropped_take_count(sampler: &Sampler) -> usize {
+        sampler.dropped_take_count()
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn sampler_convert_slots(sampler: &mut Sampler, sample_rate: f32) {
+        sampler.convert_slots(sample_rate)
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct SamplerEvent {
+        pub offset: usize,
+        pub command: Command,
+    }
+
+    impl From<SamplerEvent> for (usize, Command) {
+        fn from(event: SamplerEvent) -> Self {
+            (event.offset, event.command)
+        }
+    }
+
     #[no_mangle]
     pub unsafe extern "C" fn sampler_process_frame<'a>(
         sampler: &mut Sampler,
@@ -54,20 +189,127 @@ mod sampler1 {
         frames: usize,
         params: &Params,
     ) {
+        sampler_process_block(
+            sampler,
+            inputs,
+            outputs,
+            frames,
+            core::ptr::null(),
+            0,
+            params,
+        )
+    }
+
+    /// `events` must be sorted by offset
+    #[no_mangle]
+    pub unsafe extern "C" fn sampler_process_block(
+        sampler: &mut Sampler,
+        inputs: *mut *const f32,
+        outputs: *mut *mut f32,
+        frames: usize,
+        events: *const SamplerEvent,
+        event_count: usize,
+        params: &Params,
+    ) {
+        sampler_process_block_with_record_inputs(
+            sampler,
+            inputs,
+            inputs,
+            outputs,
+            frames,
+            events,
+            event_count,
+            params,
+        )
+    }
+
+    /// Records `record_inputs` while `inputs` are passed through. `events` must be sorted
+    /// by offset
+    #[no_mangle]
+    pub unsafe extern "C" fn sampler_process_block_with_record_inputs(
+        sampler: &mut Sampler,
+        inputs: *mut *const f32,
+        record_inputs: *mut *const f32,
+        outputs: *mut *mut f32,
+        frames: usize,
+        events: *const SamplerEvent,
+        event_count: usize,
+        params: &Params,
+    ) {
+        sampler_process_block_with_aux_outputs(
+            sampler,
+            inputs,
+            record_inputs,
+            outputs,
+            core::ptr::null_mut(),
+            0,
+            frames,
+            events,
+            event_count,
+            params,
+        )
+    }
+
+    /// `aux_outputs` holds `aux_output_count` outputs of one buffer per channel each, see
+    /// `Sampler::process_block_with_aux_outputs`. `events` must be sorted by offset
+    #[no_mangle]
+    pub unsafe extern "C" fn sampler_process_block_with_aux_outputs(
+        sampler: &mut Sampler,
+        inputs: *mut *const f32,
+        record_inputs: *mut *const f32,
+        outputs: *mut *mut f32,
+        aux_outputs: *mut *mut f32,
+        aux_output_count: usize,
+        frames: usize,
+        events: *const SamplerEvent,
+        event_count: usize,
+        params: &Params,
+    ) {
+        // no allocation for up to INLINE_CHANNELS channels
         let out: &[*mut f32] = slice::from_raw_parts(outputs, sampler.channels.len());
-        let mut out: SmallVec<&mut [f32], 2> = out
-            .into_iter()
+        let mut out: SmallVec<&mut [f32], INLINE_CHANNELS> = out
+            .iter()
             .map(|v| slice::from_raw_parts_mut(*v, frames))
             .collect();
         let inputs: &[*const f32] = slice::from_raw_parts(inputs, sampler.channels.len());
-        let inputs: SmallVec<&[f32], 2> = inputs
-            .into_iter()
+        let inputs: SmallVec<&[f32], INLINE_CHANNELS> = inputs
+            .iter()
             .map(|v| slice::from_raw_parts(*v, frames))
             .collect();
-        for channel in 0..inputs.len() {
-            for i in 0..frames {
-                out[channel][i] = sampler.process_sample(channel, inputs[channel][i], params);
-            }
-        }
+        let record_inputs: &[*const f32] =
+            slice::from_raw_parts(record_inputs, sampler.channels.len());
+        let record_inputs: SmallVec<&[f32], INLINE_CHANNELS> = record_inputs
+            .iter()
+            .map(|v| slice::from_raw_parts(*v, frames))
+            .collect();
+        let mut aux_outputs: SmallVec<&mut [f32], { MAX_AUX_OUTPUTS * INLINE_CHANNELS }> =
+            if aux_output_count == 0 {
+                SmallVec::new()
+            } else {
+                let aux_outputs =
+                    slice::from_raw_parts(aux_outputs, aux_output_count * sampler.channels.len());
+                // outputs past MAX_AUX_OUTPUTS never get a voice
+                let (used, unused) = aux_outputs
+                    .split_at(aux_output_count.min(MAX_AUX_OUTPUTS) * sampler.channels.len());
+                for v in unused {
+                    slice::from_raw_parts_mut(*v, frames).fill(0.0);
+                }
+                used.iter()
+                    .map(|v| slice::from_raw_parts_mut(*v, frames))
+                    .collect()
+            };
+        let events: &[SamplerEvent] = if event_count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(events, event_count)
+        };
+        sampler.process_block_with_aux_outputs(
+            &inputs,
+            &record_inputs,
+            &mut out,
+            &mut aux_outputs,
+            events,
+            params,
+        );
     }
 }
@@ -0,0 +1,91 @@
+// Ring buffer that keeps the most recent input at all times so it can be committed into
+// a sample slot after the fact.
+#[derive(Clone, Debug)]
+pub struct CaptureBuffer {
+    data: Vec<f32>,
+    write: usize,
+    filled: usize,
+}
+
+impl CaptureBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: vec![0.0; capacity],
+            write: 0,
+            filled: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.write = 0;
+        self.filled = 0;
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        let n = self.data.len();
+        if n == 0 {
+            return;
+        }
+        self.data[self.write] = sample;
+        self.write = (self.write + 1) % n;
+        self.filled = (self.filled + 1).min(n);
+    }
+
+    /// Replaces contents of `out` with up to `length` most recent samples, oldest first.
+    #[cfg(test)]
+    pub fn copy_last(&self, length: usize, out: &mut Vec<f32>) -> usize {
+        self.copy_before(0, length, out)
+    }
+
+    /// Replaces contents of `out` with up to `length` samples, oldest first, leaving out the
+    /// `skip` most recent ones. Returns how many samples were copied, fewer than `length` when
+    /// the buffer does not reach back far enough.
+    pub fn copy_before(&self, skip: usize, length: usize, out: &mut Vec<f32>) -> usize {
+        let n = self.data.len();
+        let length = length.min(self.filled.saturating_sub(skip));
+        out.clear();
+        if length == 0 {
+            return 0;
+        }
+        let start = (self.write + 2 * n - skip - length) % n;
+        if start + length <= n {
+            out.extend_from_slice(&self.data[start..start + length]);
+        } else {
+            out.extend_from_slice(&self.data[start..]);
+            out.extend_from_slice(&self.data[..start + length - n]);
+        }
+        length
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capture_buffer() {
+        let mut buf = CaptureBuffer::new(4);
+        let mut out = vec![];
+        buf.copy_last(4, &mut out);
+        assert!(out.is_empty());
+
+        buf.push(1.0);
+        buf.push(2.0);
+        buf.copy_last(4, &mut out);
+        assert_eq!(out, vec![1.0, 2.0]);
+
+        for x in 3..=6 {
+            buf.push(x as f32);
+        }
+        buf.copy_last(4, &mut out);
+        assert_eq!(out, vec![3.0, 4.0, 5.0, 6.0]);
+        buf.copy_last(3, &mut out);
+        assert_eq!(out, vec![4.0, 5.0, 6.0]);
+
+        // older samples, wrapping around the end of the ring
+        assert_eq!(buf.copy_before(1, 2, &mut out), 2);
+        assert_eq!(out, vec![4.0, 5.0]);
+        assert_eq!(buf.copy_before(2, 4, &mut out), 2);
+        assert_eq!(out, vec![3.0, 4.0]);
+    }
+}
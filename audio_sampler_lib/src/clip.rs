@@ -184,6 +184,47 @@ impl Clip {
         x1
     }
 
+    // Data offset of the material mirrored across the nearest loop boundary and its
+    // crossfade weight, None when playback is further than `length` from a boundary.
+    // In Loop mode the weight reaches 1.0 just before wrapping, so the jump to the loop
+    // start continues from the material that preceded it.
+    pub fn crossfade(&self, now: usize, length: T) -> Option<(T, f32)> {
+        let l = self.length;
+        let x = self.clip_offset(now);
+        let (other, weight) = match self.mode {
+            Mode::Loop => {
+                let length = length.min(l);
+                if length < ONE || x < l - length {
+                    return None;
+                }
+                (x - l, (x - (l - length) + ONE) / length)
+            }
+            Mode::PingPong => {
+                // half weight at the turning point leaves the output symmetric around it
+                let length = length.min(l * 0.5);
+                if length < ONE {
+                    return None;
+                }
+                let to_end = l - ONE - x;
+                if to_end < length {
+                    (TWO * l - ONE - x, 0.5 * (ONE - to_end / length))
+                } else if x < length {
+                    (-ONE - x, 0.5 * (ONE - x / length))
+                } else {
+                    return None;
+                }
+            }
+        };
+        let other = (self.start + other).rem_euclid(self.data_length);
+        // rem_euclid can round up to data_length for tiny negative values
+        let other = if other >= self.data_length {
+            ZERO
+        } else {
+            other
+        };
+        Some((other, weight.min(ONE)))
+    }
+
     pub fn offset(&self, now: usize) -> T {
         let x = self.clip_offset(now);
         let x = (self.start + x) % (self.data_length as T);
@@ -70,6 +70,276 @@ impl EnumIndex for LoopMode {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Interpolation {
+    None,
+    Linear,
+    CubicHermite,
+    WindowedSinc,
+}
+
+impl EnumIndex for Interpolation {
+    const LENGTH: usize = 4;
+    fn to_index(&self) -> usize {
+        match self {
+            Interpolation::None => 0,
+            Interpolation::Linear => 1,
+            Interpolation::CubicHermite => 2,
+            Interpolation::WindowedSinc => 3,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Interpolation::None,
+            1 => Interpolation::Linear,
+            2 => Interpolation::CubicHermite,
+            3 => Interpolation::WindowedSinc,
+            _ => panic!("Invalid index for Interpolation"),
+        }
+    }
+}
+
+// what happens when a note that is already playing is triggered again
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Retrigger {
+    // restart the playing voice from the new position
+    Restart,
+    // start another voice alongside the playing one
+    Layer,
+    // keep the playing voice as is
+    Ignore,
+}
+
+impl EnumIndex for Retrigger {
+    const LENGTH: usize = 3;
+    fn to_index(&self) -> usize {
+        match self {
+            Retrigger::Restart => 0,
+            Retrigger::Layer => 1,
+            Retrigger::Ignore => 2,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Retrigger::Restart,
+            1 => Retrigger::Layer,
+            2 => Retrigger::Ignore,
+            _ => panic!("Invalid index for Retrigger"),
+        }
+    }
+}
+
+// sample format of exported WAV files
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WavFormat {
+    Float32,
+    Pcm24,
+    Pcm16,
+}
+
+impl EnumIndex for WavFormat {
+    const LENGTH: usize = 3;
+    fn to_index(&self) -> usize {
+        match self {
+            WavFormat::Float32 => 0,
+            WavFormat::Pcm24 => 1,
+            WavFormat::Pcm16 => 2,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => WavFormat::Float32,
+            1 => WavFormat::Pcm24,
+            2 => WavFormat::Pcm16,
+            _ => panic!("Invalid index for WavFormat"),
+        }
+    }
+}
+
+// Resample couples speed and pitch, Granular plays the loop at `speed` while
+// grains are pitched independently by `pitch`
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PlaybackMode {
+    Resample,
+    Granular,
+}
+
+impl EnumIndex for PlaybackMode {
+    const LENGTH: usize = 2;
+    fn to_index(&self) -> usize {
+        match self {
+            PlaybackMode::Resample => 0,
+            PlaybackMode::Granular => 1,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => PlaybackMode::Resample,
+            1 => PlaybackMode::Granular,
+            _ => panic!("Invalid index for PlaybackMode"),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum VoiceStealing {
+    Oldest,
+    Quietest,
+    // voice playing the same note, oldest if there is none
+    SameNote,
+    // released voices first, then the lowest note
+    LowestPriority,
+}
+
+impl EnumIndex for VoiceStealing {
+    const LENGTH: usize = 4;
+    fn to_index(&self) -> usize {
+        match self {
+            VoiceStealing::Oldest => 0,
+            VoiceStealing::Quietest => 1,
+            VoiceStealing::SameNote => 2,
+            VoiceStealing::LowestPriority => 3,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => VoiceStealing::Oldest,
+            1 => VoiceStealing::Quietest,
+            2 => VoiceStealing::SameNote,
+            3 => VoiceStealing::LowestPriority,
+            _ => panic!("Invalid index for VoiceStealing"),
+        }
+    }
+}
+
+// which aux output a new voice plays on, voices are always in the main output too
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputRouting {
+    Off,
+    Slice,
+    // consecutive ranges of `notes_per_output` notes from `output_base_note`
+    NoteRange,
+    RoundRobin,
+}
+
+impl EnumIndex for OutputRouting {
+    const LENGTH: usize = 4;
+    fn to_index(&self) -> usize {
+        match self {
+            OutputRouting::Off => 0,
+            OutputRouting::Slice => 1,
+            OutputRouting::NoteRange => 2,
+            OutputRouting::RoundRobin => 3,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => OutputRouting::Off,
+            1 => OutputRouting::Slice,
+            2 => OutputRouting::NoteRange,
+            3 => OutputRouting::RoundRobin,
+            _ => panic!("Invalid index for OutputRouting"),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EnvelopeCurve {
+    Linear,
+    Exponential,
+    SCurve,
+}
+
+impl EnumIndex for EnvelopeCurve {
+    const LENGTH: usize = 3;
+    fn to_index(&self) -> usize {
+        match self {
+            EnvelopeCurve::Linear => 0,
+            EnvelopeCurve::Exponential => 1,
+            EnvelopeCurve::SCurve => 2,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => EnvelopeCurve::Linear,
+            1 => EnvelopeCurve::Exponential,
+            2 => EnvelopeCurve::SCurve,
+            _ => panic!("Invalid index for EnvelopeCurve"),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RecordingMode {
+    Free,
+    FixedLength,
+    Overdub,
+}
+
+impl EnumIndex for RecordingMode {
+    const LENGTH: usize = 3;
+    fn to_index(&self) -> usize {
+        match self {
+            RecordingMode::Free => 0,
+            RecordingMode::FixedLength => 1,
+            RecordingMode::Overdub => 2,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => RecordingMode::Free,
+            1 => RecordingMode::FixedLength,
+            2 => RecordingMode::Overdub,
+            _ => panic!("Invalid index for RecordingMode"),
+        }
+    }
+}
+
+/// How pitch offsets in semitones map to playback speed
+#[repr(C)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PitchTracking {
+    // +12 semitones doubles the speed, -12 stops and -24 plays backwards
+    Linear,
+    // 2^(semitones/12), every octave doubles or halves the speed
+    Exponential,
+}
+
+impl EnumIndex for PitchTracking {
+    const LENGTH: usize = 2;
+    fn to_index(&self) -> usize {
+        match self {
+            PitchTracking::Linear => 0,
+            PitchTracking::Exponential => 1,
+        }
+    }
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => PitchTracking::Linear,
+            1 => PitchTracking::Exponential,
+            _ => panic!("Invalid index for PitchTracking"),
+        }
+    }
+}
+
+impl PitchTracking {
+    /// Speed multiplier for a pitch offset, `multiplier` scales the offset
+    pub fn speed(&self, semitones: f32, multiplier: f32) -> f32 {
+        match self {
+            PitchTracking::Linear => 1.0 + (semitones / 12.0) * multiplier,
+            PitchTracking::Exponential => 2.0_f32.powf(semitones * multiplier / 12.0),
+        }
+    }
+}
+
 // #[cfg(test)]
 // mod test {
 //     use super::*;
@@ -97,7 +367,11 @@ impl EnumIndex for LoopMode {
 #[derive(Debug, Clone)]
 pub struct Params {
     pub attack_samples: usize,
+    pub hold_samples: usize,
     pub decay_samples: usize,
+    pub sustain_level: f32,
+    pub release_samples: usize,
+    pub envelope_curve: EnvelopeCurve,
     pub auto_passthru: bool,
     pub loop_mode: LoopMode,
     pub loop_length: TimeOrRatio,
@@ -109,6 +383,29 @@ pub struct Params {
     pub reverse_speed: f32,
     pub note_off_behavior: NoteOffBehaviour,
     pub volume: f32,
+    pub interpolation: Interpolation,
+    pub recording_mode: RecordingMode,
+    pub overdub_feedback: f32,
+    pub capture_length: TimeValue,
+    // 0 means MAX_VOICES
+    pub max_voices: usize,
+    pub voice_stealing: VoiceStealing,
+    pub retrigger: Retrigger,
+    pub playback_mode: PlaybackMode,
+    // pitch ratio, in Resample mode this also changes speed
+    pub pitch: f32,
+    pub grain_samples: usize,
+    // 0 disables crossfading at loop boundaries
+    pub loop_crossfade_samples: usize,
+    // 0.0 disables onset detection, slices then follow an equal grid
+    pub onset_sensitivity: f32,
+    // length of grid slices, a ratio is the fraction of the buffer each slice takes
+    pub slice_length: TimeOrRatio,
+    pub output_routing: OutputRouting,
+    // number of aux outputs voices are spread over, 0 keeps everything in the main output
+    pub output_count: usize,
+    pub output_base_note: u8,
+    pub notes_per_output: usize,
 }
 
 impl Params {
@@ -146,9 +443,42 @@ impl Params {
         );
         length.max(1.0)
     }
+
+    /// Fraction of the buffer each grid slice takes, at least one sample
+    pub fn slice_ratio(&self, data_len: usize) -> f32 {
+        let ratio = match self.slice_length {
+            TimeOrRatio::Ratio(ratio) => ratio,
+            TimeOrRatio::Time(time) if data_len > 0 => {
+                time.as_samples(&self.transport) / data_len as f32
+            }
+            TimeOrRatio::Time(_) => 1.0,
+        };
+        let min_ratio = if data_len > 0 {
+            1.0 / data_len as f32
+        } else {
+            f32::EPSILON
+        };
+        ratio.clamp(min_ratio, 1.0)
+    }
+
+    /// Number of grid slices in the buffer, the last one may be shorter than the others
+    pub fn slice_count(&self, data_len: usize) -> usize {
+        // tolerance keeps rounding errors from adding an empty slice at the end
+        (1.0 / self.slice_ratio(data_len) - 1e-3).ceil().max(1.0) as usize
+    }
 }
 
 pub const DEFAULT_AUTO_PASSTHRU: bool = true;
+pub const DEFAULT_SLOT_COUNT: usize = 8;
+pub const DEFAULT_SLICE_COUNT: usize = 16;
+// most voices playing at once, more steal and fade out existing ones
+pub const MAX_VOICES: usize = 64;
+// voice storage is allocated up front, with room for the fade tails of stolen voices
+pub const MAX_VOICE_STORAGE: usize = 2 * MAX_VOICES;
+// aux outputs beyond this are left silent
+pub const MAX_AUX_OUTPUTS: usize = 16;
+// 30 seconds at 48 kHz
+pub const DEFAULT_MAX_RECORDING_SAMPLES: usize = 1_440_000;
 
 #[repr(C)]
 #[derive(Debug, Clone)]
@@ -180,7 +510,11 @@ impl Default for Params {
             loop_mode: LoopMode::Loop,
             loop_length: TimeOrRatio::Ratio(1.0),
             start_offset_percent: 0.0,
-            decay_samples: 100,
+            hold_samples: 0,
+            decay_samples: 0,
+            sustain_level: 1.0,
+            release_samples: 100,
+            envelope_curve: EnvelopeCurve::Linear,
             speed: 1.0,
             reverse_speed: 1.0,
             fixed_size_samples: 0,
@@ -188,6 +522,23 @@ impl Default for Params {
             transport: Transport::default(),
             note_off_behavior: NoteOffBehaviour::DecayAndZeroCrossing,
             volume: 1.0,
+            interpolation: Interpolation::None,
+            recording_mode: RecordingMode::Free,
+            overdub_feedback: 1.0,
+            capture_length: TimeValue::Bars(1.0),
+            max_voices: 0,
+            voice_stealing: VoiceStealing::Oldest,
+            retrigger: Retrigger::Layer,
+            playback_mode: PlaybackMode::Resample,
+            pitch: 1.0,
+            grain_samples: 2048,
+            loop_crossfade_samples: 0,
+            onset_sensitivity: 0.0,
+            slice_length: TimeOrRatio::Ratio(1.0 / DEFAULT_SLICE_COUNT as f32),
+            output_routing: OutputRouting::Off,
+            output_count: 0,
+            output_base_note: 12,
+            notes_per_output: 4,
         }
     }
 }
@@ -195,12 +546,24 @@ impl Default for Params {
 #[repr(C)]
 pub struct InitParams {
     pub auto_passthru: bool,
+    pub slot_count: usize,
+    // size of the always-on capture buffer, 0 disables it
+    pub capture_buffer_samples: usize,
+    // the spare buffer takes are written into is preallocated to this length, takes end when
+    // they reach it
+    pub max_recording_samples: usize,
+    // rate slots are assumed to be at until something is recorded or loaded into them
+    pub sample_rate: f32,
 }
 
 impl Default for InitParams {
     fn default() -> Self {
         InitParams {
             auto_passthru: DEFAULT_AUTO_PASSTHRU,
+            slot_count: DEFAULT_SLOT_COUNT,
+            capture_buffer_samples: 0,
+            max_recording_samples: DEFAULT_MAX_RECORDING_SAMPLES,
+            sample_rate: 44100.0,
         }
     }
 }
@@ -211,6 +574,7 @@ pub struct VersionedWaveformSummary {
     pub waveform_summary: WaveformSummary,
 }
 
+#[repr(C)]
 #[derive(Hash, PartialEq, Clone, Copy, Default, Debug)]
 pub struct Note {
     pub note: u8,
@@ -234,3 +598,51 @@ impl Note {
         }
     }
 }
+
+/// Applied by `Sampler::process_block` at a sample offset within the block
+#[repr(C)]
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Command {
+    // reverse plays the voice backwards, on top of any global reverse
+    StartPlaying {
+        pos: f32,
+        note: Note,
+        velocity: f32,
+        reverse: bool,
+    },
+    StartPlayingSlice {
+        slice: usize,
+        note: Note,
+        velocity: f32,
+        reverse: bool,
+    },
+    // from the start of the slot to its end, ignoring the loop length
+    StartPlayingWhole {
+        note: Note,
+        velocity: f32,
+        reverse: bool,
+    },
+    StopPlaying {
+        note: Note,
+    },
+    StartRecording,
+    StopRecording,
+    CommitCapture,
+    SetNoteSpeed {
+        note: Note,
+        speed: f32,
+    },
+    // the following override params for the rest of the block
+    SetSpeed {
+        speed: f32,
+    },
+    SetPitch {
+        pitch: f32,
+    },
+    SetReverse {
+        reverse: bool,
+    },
+    SelectSlot {
+        slot: usize,
+    },
+}
@@ -0,0 +1,217 @@
+use std::ops::Range;
+
+use crate::common_types::{EnvelopeCurve, Params};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Stage {
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+// Per voice AHDSR envelope. Stage durations and levels are latched on note-on,
+// release time is taken on note-off.
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    stage: Stage,
+    time: Range<usize>,
+    value: Range<f32>,
+    curve: EnvelopeCurve,
+    attack_samples: usize,
+    hold_samples: usize,
+    decay_samples: usize,
+    release_samples: usize,
+    peak: f32,
+    sustain: f32,
+}
+
+fn shape(curve: EnvelopeCurve, t: f32) -> f32 {
+    match curve {
+        EnvelopeCurve::Linear => t,
+        EnvelopeCurve::Exponential => {
+            const K: f32 = 5.0;
+            (1.0 - (-K * t).exp()) / (1.0 - (-K).exp())
+        }
+        EnvelopeCurve::SCurve => t * t * (3.0 - 2.0 * t),
+    }
+}
+
+impl Envelope {
+    pub fn new(now: usize, velocity: f32, params: &Params) -> Self {
+        Self::starting_from(now, 0.0, velocity, params)
+    }
+
+    // restarts attack from the current level so retriggered voices don't click
+    pub fn retrigger(&mut self, now: usize, velocity: f32, params: &Params) {
+        *self = Self::starting_from(now, self.value(now), velocity, params);
+    }
+
+    fn starting_from(now: usize, from: f32, velocity: f32, params: &Params) -> Self {
+        let mut env = Envelope {
+            stage: Stage::Off,
+            time: now..now,
+            value: 0.0..0.0,
+            curve: params.envelope_curve,
+            attack_samples: params.attack_samples,
+            hold_samples: params.hold_samples,
+            decay_samples: params.decay_samples,
+            release_samples: params.release_samples,
+            peak: velocity,
+            sustain: velocity * params.sustain_level,
+        };
+        env.enter(now, Stage::Attack, from);
+        env
+    }
+
+    #[allow(unused)]
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.stage == Stage::Off
+    }
+
+    fn enter(&mut self, now: usize, stage: Stage, from: f32) {
+        let (duration, to) = match stage {
+            Stage::Attack => (self.attack_samples, self.peak),
+            Stage::Hold => (self.hold_samples, self.peak),
+            Stage::Decay => (self.decay_samples, self.sustain),
+            Stage::Sustain => (0, self.sustain),
+            Stage::Release => (self.release_samples, 0.0),
+            Stage::Off => (0, 0.0),
+        };
+        self.stage = stage;
+        self.time = now..(now + duration);
+        self.value = from..to;
+        if duration == 0 {
+            if let Some(next) = Self::next_stage(stage) {
+                self.enter(now, next, to);
+            }
+        }
+    }
+
+    fn next_stage(stage: Stage) -> Option<Stage> {
+        match stage {
+            Stage::Attack => Some(Stage::Hold),
+            Stage::Hold => Some(Stage::Decay),
+            Stage::Decay => Some(Stage::Sustain),
+            Stage::Release => Some(Stage::Off),
+            Stage::Sustain | Stage::Off => None,
+        }
+    }
+
+    pub fn release(&mut self, now: usize, release_samples: usize) {
+        let from = self.value(now);
+        self.release_samples = release_samples;
+        self.enter(now, Stage::Release, from);
+    }
+
+    pub fn value(&self, now: usize) -> f32 {
+        match self.stage {
+            Stage::Sustain | Stage::Off => self.value.end,
+            _ => {
+                let t = (now - self.time.start) as f32 / (self.time.end - self.time.start) as f32;
+                let t = t.clamp(0.0, 1.0);
+                self.value.start + (self.value.end - self.value.start) * shape(self.curve, t)
+            }
+        }
+    }
+
+    pub fn step(&mut self, now: usize) {
+        match self.stage {
+            Stage::Sustain | Stage::Off => (),
+            stage => {
+                assert!(now >= self.time.start);
+                if now >= self.time.end {
+                    if let Some(next) = Self::next_stage(stage) {
+                        self.enter(now, next, self.value.end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(env: &mut Envelope, from: usize, n: usize) -> Vec<f32> {
+        (from..from + n)
+            .map(|now| {
+                let value = env.value(now);
+                env.step(now);
+                value
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_envelope_stages() {
+        let params = Params {
+            attack_samples: 2,
+            hold_samples: 2,
+            decay_samples: 2,
+            sustain_level: 0.5,
+            envelope_curve: EnvelopeCurve::Linear,
+            ..Params::default()
+        };
+        let mut env = Envelope::new(0, 1.0, &params);
+        assert_eq!(
+            run(&mut env, 0, 8),
+            vec![0.0, 0.5, 1.0, 1.0, 1.0, 0.75, 0.5, 0.5]
+        );
+        assert_eq!(env.stage(), Stage::Sustain);
+        env.release(8, 2);
+        assert_eq!(run(&mut env, 8, 4), vec![0.5, 0.25, 0.0, 0.0]);
+        assert!(env.is_off());
+    }
+
+    #[test]
+    fn test_envelope_retrigger() {
+        let params = Params {
+            attack_samples: 4,
+            sustain_level: 1.0,
+            envelope_curve: EnvelopeCurve::Linear,
+            ..Params::default()
+        };
+        let mut env = Envelope::new(0, 1.0, &params);
+        assert_eq!(run(&mut env, 0, 4), vec![0.0, 0.25, 0.5, 0.75]);
+        env.release(4, 4);
+        assert_eq!(run(&mut env, 4, 2), vec![1.0, 0.75]);
+        env.retrigger(6, 1.0, &params);
+        assert_eq!(run(&mut env, 6, 3), vec![0.5, 0.625, 0.75]);
+    }
+
+    #[test]
+    fn test_envelope_zero_durations() {
+        let params = Params {
+            attack_samples: 0,
+            hold_samples: 0,
+            decay_samples: 0,
+            sustain_level: 1.0,
+            ..Params::default()
+        };
+        let mut env = Envelope::new(0, 0.8, &params);
+        assert_eq!(env.stage(), Stage::Sustain);
+        assert_eq!(env.value(0), 0.8);
+        env.release(1, 0);
+        assert!(env.is_off());
+    }
+
+    #[test]
+    fn test_envelope_curves_keep_end_points() {
+        for curve in [
+            EnvelopeCurve::Linear,
+            EnvelopeCurve::Exponential,
+            EnvelopeCurve::SCurve,
+        ] {
+            assert!((shape(curve, 0.0)).abs() < 1e-6);
+            assert!((shape(curve, 1.0) - 1.0).abs() < 1e-6);
+        }
+    }
+}
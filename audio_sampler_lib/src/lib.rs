@@ -1,12 +1,24 @@
 extern crate core;
 
+mod aiff;
+pub mod audio_file;
 mod capi;
+mod capture;
 mod clip;
 pub mod common_types;
+mod envelope;
+mod grain;
+mod interpolation;
+mod onset;
 mod recorder;
+mod routing;
 pub mod sampler;
+pub mod scratch;
+pub mod shared;
+pub mod snapshot;
 mod test_perf;
 mod test_sampler;
 pub mod time_value;
 mod voice;
 mod volume;
+pub mod wav;
@@ -0,0 +1,123 @@
+// Energy based onset detector used to place slice points on recorded material.
+
+// hop size of the energy envelope
+const FRAME: usize = 512;
+// onsets closer than this to the previous one are merged into it
+const MIN_GAP_FRAMES: usize = 4;
+// range of the log-energy rise threshold that sensitivity maps onto
+const MIN_THRESHOLD: f32 = 0.25;
+const MAX_THRESHOLD: f32 = 4.0;
+// onset sample is the first one in the onset frame reaching this fraction of its peak
+const ATTACK_FRACTION: f32 = 0.25;
+
+fn frame_energies(channels: &[&[f32]], frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|k| {
+            let range = k * FRAME..((k + 1) * FRAME);
+            channels
+                .iter()
+                .map(|data| {
+                    let end = range.end.min(data.len());
+                    let start = range.start.min(end);
+                    data[start..end].iter().map(|x| x * x).sum::<f32>()
+                })
+                .sum::<f32>()
+                / FRAME as f32
+        })
+        .collect()
+}
+
+// finds where the attack starts within the frame, so slices don't cut off its beginning
+fn refine(channels: &[&[f32]], frame: usize, len: usize) -> usize {
+    let start = frame.saturating_sub(1) * FRAME;
+    let end = ((frame + 1) * FRAME).min(len);
+    let peak_at = |i: usize| {
+        channels
+            .iter()
+            .map(|data| data.get(i).map_or(0.0, |x| x.abs()))
+            .fold(0.0, f32::max)
+    };
+    let peak = (start..end).map(peak_at).fold(0.0, f32::max);
+    (start..end)
+        .find(|&i| peak_at(i) >= peak * ATTACK_FRACTION)
+        .unwrap_or(frame * FRAME)
+}
+
+/// Returns sample offsets of detected onsets, always starting at 0. Sensitivity is in
+/// 0.0..=1.0 where 0.0 disables detection. An empty result means nothing but the start
+/// of the buffer was found and callers should fall back to an equal grid.
+pub fn detect_onsets(channels: &[&[f32]], sensitivity: f32) -> Vec<usize> {
+    let len = channels.iter().map(|data| data.len()).max().unwrap_or(0);
+    if sensitivity <= 0.0 || len == 0 {
+        return vec![];
+    }
+    let frames = len.div_ceil(FRAME);
+    let energies = frame_energies(channels, frames);
+    let max_energy = energies.iter().cloned().fold(0.0, f32::max);
+    if max_energy == 0.0 {
+        return vec![];
+    }
+    // relative floor keeps noise after silence from looking like a large rise
+    let floor = max_energy * 1e-4;
+    let rise: Vec<f32> = (0..frames)
+        .map(|k| {
+            if k == 0 {
+                0.0
+            } else {
+                ((energies[k] + floor) / (energies[k - 1] + floor))
+                    .ln()
+                    .max(0.0)
+            }
+        })
+        .collect();
+    let threshold = MIN_THRESHOLD + (1.0 - sensitivity.min(1.0)) * (MAX_THRESHOLD - MIN_THRESHOLD);
+
+    let mut onsets = vec![0];
+    let mut last_frame = 0;
+    for k in 1..frames {
+        let is_peak = rise[k] >= rise[k - 1] && (k + 1 == frames || rise[k] > rise[k + 1]);
+        if rise[k] >= threshold && is_peak && k - last_frame >= MIN_GAP_FRAMES {
+            let offset = refine(channels, k, len);
+            if offset > *onsets.last().unwrap() {
+                onsets.push(offset);
+                last_frame = k;
+            }
+        }
+    }
+    if onsets.len() < 2 {
+        return vec![];
+    }
+    onsets
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hits(len: usize, at: &[usize]) -> Vec<f32> {
+        let mut data = vec![0.001; len];
+        for &start in at {
+            for i in 0..2000.min(len - start) {
+                data[start + i] = (1.0 - i as f32 / 2000.0) * if i % 2 == 0 { 1.0 } else { -1.0 };
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_detect_onsets() {
+        let data = hits(20000, &[0, 5000, 12345]);
+        let onsets = detect_onsets(&[&data], 0.5);
+        assert_eq!(onsets, vec![0, 5000, 12345]);
+    }
+
+    #[test]
+    fn test_detect_onsets_fallback() {
+        let data = hits(20000, &[5000]);
+        assert_eq!(detect_onsets(&[&data], 0.0), Vec::<usize>::new());
+        let silence = vec![0.0; 20000];
+        assert_eq!(detect_onsets(&[&silence], 1.0), Vec::<usize>::new());
+        let constant = vec![0.5; 20000];
+        assert_eq!(detect_onsets(&[&constant], 1.0), Vec::<usize>::new());
+    }
+}
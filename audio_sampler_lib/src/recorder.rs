@@ -1,8 +1,15 @@
 use crate::common_types;
+use crate::common_types::RecordingMode;
+use crate::time_value::calc_samples_per_bar;
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum State {
     Triggered { write: usize },
+    // waiting for the next bar boundary before starting a fixed length take
+    Scheduled { wait: usize, length: usize },
+    Fixed { write: usize, length: usize },
+    // mixes into the existing buffer, wrapping around at its end
+    Overdub { write: usize, feedback: f32 },
     Idle,
 }
 
@@ -10,6 +17,9 @@ pub struct Params {
     pub transport_pos_samples: f32,
     pub sample_id: usize,
     pub fixed_size_samples: usize,
+    pub samples_per_bar: f32,
+    pub recording_mode: RecordingMode,
+    pub overdub_feedback: f32,
 }
 
 impl From<&common_types::Params> for Params {
@@ -18,6 +28,9 @@ impl From<&common_types::Params> for Params {
             transport_pos_samples: params.transport.pos_samples,
             sample_id: params.sample_id,
             fixed_size_samples: params.fixed_size_samples,
+            samples_per_bar: calc_samples_per_bar(&params.transport),
+            recording_mode: params.recording_mode,
+            overdub_feedback: params.overdub_feedback,
         }
     }
 }
@@ -29,93 +42,213 @@ impl Params {
             transport_pos_samples: transport_pos_samples as f32,
             sample_id: self.sample_id,
             fixed_size_samples: self.fixed_size_samples,
+            samples_per_bar: self.samples_per_bar,
+            recording_mode: self.recording_mode,
+            overdub_feedback: self.overdub_feedback,
         }
     }
+
+    fn samples_until_next_bar(&self) -> usize {
+        let bar = self.samples_per_bar;
+        if bar <= 0.0 {
+            return 0;
+        }
+        let pos = self.transport_pos_samples;
+        let next = (pos / bar).ceil() * bar;
+        (next - pos).round().max(0.0) as usize
+    }
 }
 
+// counters only, errors are recorded on the audio thread and must not allocate
 #[derive(Default, Clone, Debug)]
 pub struct RecorderErrors {
-    skipped_samples: Vec<(usize, usize)>,
-    negative_transport_pos: Vec<f32>,
-    incorrect_state: Vec<(State, State)>,
+    skipped_samples: usize,
+    negative_transport_pos: usize,
+    incorrect_state: usize,
+    last_incorrect_state: Option<(State, State)>,
+    // takes cut short because the buffer was full
+    overflows: usize,
+}
+
+impl RecorderErrors {
+    fn record_incorrect_state(&mut self, expected: State, actual: State) {
+        self.incorrect_state += 1;
+        self.last_incorrect_state = Some((expected, actual));
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Recorder {
     pub(crate) state: State,
     pub(crate) errors: RecorderErrors,
+    // takes never grow a buffer past this length or its capacity
+    max_samples: usize,
 }
 
 impl Recorder {
-    pub fn new() -> Self {
+    pub fn new(max_samples: usize) -> Self {
         Self {
             state: State::Idle,
             errors: RecorderErrors::default(),
+            max_samples,
         }
     }
 
+    pub fn max_samples(&self) -> usize {
+        self.max_samples
+    }
+
+    pub fn overflow_count(&self) -> usize {
+        self.errors.overflows
+    }
+
     pub fn print_error_info(&self) -> String {
         format!(
-            "skip: {:?}, neg: {:?}, inc: {:?}",
-            self.errors.skipped_samples.len(),
-            self.errors.negative_transport_pos.len(),
-            self.errors.incorrect_state.len()
+            "skip: {:?}, neg: {:?}, inc: {:?} (last: {:?}), overflow: {:?}",
+            self.errors.skipped_samples,
+            self.errors.negative_transport_pos,
+            self.errors.incorrect_state,
+            self.errors.last_incorrect_state,
+            self.errors.overflows
         )
     }
 
-    pub fn stop(&mut self, data: &mut Vec<f32>, _params: &Params) {
+    pub fn stop(&mut self, data: &mut Vec<f32>, params: &Params) {
         match self.state {
             State::Triggered { write } => {
                 data.truncate(write);
                 self.state = State::Idle;
             }
+            // fixed length takes always run to completion and stop by themselves, even when
+            // released before the bar boundary they wait for
+            State::Scheduled { .. } | State::Fixed { .. } => (),
+            // overdubbing never changes the length of the buffer
+            State::Overdub { .. } => {
+                self.state = State::Idle;
+            }
+            State::Idle if params.recording_mode == RecordingMode::FixedLength => (),
             _ => {
                 self.errors
-                    .incorrect_state
-                    .push((State::Triggered { write: 0 }, self.state.clone()));
+                    .record_incorrect_state(State::Triggered { write: 0 }, self.state.clone());
             }
         }
     }
 
     pub fn last_recorded_offset(&self) -> Option<usize> {
         match self.state {
-            State::Triggered { write, .. } => Some(write),
+            State::Triggered { write, .. }
+            | State::Fixed { write, .. }
+            | State::Overdub { write, .. } => Some(write),
             _ => None,
         }
     }
 
     pub fn is_recording(&self) -> bool {
-        match self.state {
-            State::Triggered { .. } => true,
-            _ => false,
-        }
+        matches!(
+            self.state,
+            State::Triggered { .. } | State::Fixed { .. } | State::Overdub { .. }
+        )
     }
 
-    pub fn start(&mut self) {
+    pub fn is_scheduled(&self) -> bool {
+        matches!(self.state, State::Scheduled { .. })
+    }
+
+    pub fn start(&mut self, params: &Params) {
         match self.state {
             State::Idle => {
-                self.state = State::Triggered { write: 0 };
+                self.state = match params.recording_mode {
+                    RecordingMode::FixedLength if params.fixed_size_samples > 0 => {
+                        State::Scheduled {
+                            wait: params.samples_until_next_bar(),
+                            length: params.fixed_size_samples,
+                        }
+                    }
+                    // feedback is latched for the duration of the take, writing starts where
+                    // the transport is in the loop and wraps with the buffer length
+                    RecordingMode::Overdub => State::Overdub {
+                        write: params.transport_pos_samples.max(0.0) as usize,
+                        feedback: params.overdub_feedback,
+                    },
+                    _ => State::Triggered { write: 0 },
+                };
             }
             _ => {
                 self.errors
-                    .incorrect_state
-                    .push((State::Idle, self.state.clone()));
+                    .record_incorrect_state(State::Idle, self.state.clone());
+            }
+        }
+    }
+
+    // returns false when the buffer is full, pushing never reallocates
+    fn write_sample(&self, sample: f32, data: &mut Vec<f32>, i: usize) -> bool {
+        let n = data.len();
+        assert!(i <= n);
+        if i == n {
+            if n >= self.max_samples || n >= data.capacity() {
+                return false;
             }
+            data.push(sample);
+        } else {
+            data[i] = sample;
         }
+        true
+    }
+
+    fn overflow(&mut self, data: &mut Vec<f32>, write: usize) {
+        data.truncate(write);
+        self.errors.overflows += 1;
+        self.state = State::Idle;
     }
 
     pub fn process_sample(&mut self, sample: f32, data: &mut Vec<f32>) {
-        match &mut self.state {
+        if let State::Scheduled { wait, length } = self.state {
+            if wait > 0 {
+                self.state = State::Scheduled {
+                    wait: wait - 1,
+                    length,
+                };
+                return;
+            }
+            self.state = State::Fixed { write: 0, length };
+        }
+        if let State::Overdub { .. } = self.state {
+            // nothing to overdub onto, first pass defines the loop
+            if data.is_empty() {
+                self.state = State::Triggered { write: 0 };
+            }
+        }
+        match self.state {
             State::Triggered { write } => {
-                let n = data.len();
-                let i = *write;
-                assert!(i <= n);
-                if i == n {
-                    data.push(sample);
+                if !self.write_sample(sample, data, write) {
+                    self.overflow(data, write);
+                    return;
+                }
+                self.state = State::Triggered { write: write + 1 };
+            }
+            State::Fixed { write, length } => {
+                if !self.write_sample(sample, data, write) {
+                    self.overflow(data, write);
+                    return;
+                }
+                if write + 1 == length {
+                    data.truncate(length);
+                    self.state = State::Idle;
                 } else {
-                    data[i] = sample;
+                    self.state = State::Fixed {
+                        write: write + 1,
+                        length,
+                    };
                 }
-                *write += 1;
+            }
+            State::Overdub { write, feedback } => {
+                // buffer may have been shortened by another take since the last sample
+                let i = write % data.len();
+                data[i] = data[i] * feedback + sample;
+                self.state = State::Overdub {
+                    write: (i + 1) % data.len(),
+                    feedback,
+                };
             }
             _ => (),
         }
@@ -128,25 +261,140 @@ mod test {
 
     #[test]
     fn test_recorder() {
-        let mut rec = Recorder::new();
-        let mut data = vec![0.0; 10];
+        let mut rec = Recorder::new(100);
+        let mut data = Vec::with_capacity(100);
+        data.resize(10, 0.0);
         let params = Params {
             transport_pos_samples: 0.0,
             fixed_size_samples: 100,
             sample_id: 0,
+            samples_per_bar: 100.0,
+            recording_mode: RecordingMode::Free,
+            overdub_feedback: 1.0,
         };
         let params = &params;
         rec.process_sample(1.0, &mut data);
         assert!(data.iter().all(|&x| x == 0.0));
-        rec.start();
+        rec.start(params);
         for i in 1..20 {
             rec.process_sample(i as f32, &mut data);
         }
         rec.stop(&mut data, params);
         rec.process_sample(0.0, &mut data);
         assert_eq!(data, (1..20).map(|x| x as f32).collect::<Vec<_>>());
-        rec.start();
+        rec.start(params);
         rec.process_sample(100.0, &mut data);
         assert_eq!(data[0], 100.0);
     }
+
+    #[test]
+    fn test_recorder_fixed_length() {
+        let mut rec = Recorder::new(100);
+        let mut data = Vec::with_capacity(100);
+        let params = Params {
+            transport_pos_samples: 0.0,
+            fixed_size_samples: 5,
+            sample_id: 0,
+            samples_per_bar: 4.0,
+            recording_mode: RecordingMode::FixedLength,
+            overdub_feedback: 1.0,
+        };
+
+        // 3 samples until the next bar, then exactly 5 samples are recorded
+        rec.start(&params.with_transport_pos_samples(5));
+        for i in 1..20 {
+            rec.process_sample(i as f32, &mut data);
+            if i == 6 {
+                rec.stop(&mut data, &params);
+            }
+        }
+        assert!(!rec.is_recording());
+        assert_eq!(data, vec![4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(rec.errors.incorrect_state, 0);
+
+        // releasing before the bar boundary still records the whole take
+        rec.start(&params.with_transport_pos_samples(1));
+        rec.process_sample(100.0, &mut data);
+        rec.stop(&mut data, &params);
+        assert!(rec.is_scheduled());
+        for i in 1..20 {
+            rec.process_sample(i as f32, &mut data);
+        }
+        assert_eq!(data, vec![3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(rec.errors.incorrect_state, 0);
+    }
+
+    #[test]
+    fn test_recorder_overdub() {
+        let mut rec = Recorder::new(100);
+        let mut data = Vec::with_capacity(100);
+        let params = Params {
+            transport_pos_samples: 0.0,
+            fixed_size_samples: 0,
+            sample_id: 0,
+            samples_per_bar: 4.0,
+            recording_mode: RecordingMode::Overdub,
+            overdub_feedback: 0.5,
+        };
+
+        // first pass into an empty buffer defines the loop length
+        rec.start(&params);
+        for x in [2.0, 4.0, 6.0] {
+            rec.process_sample(x, &mut data);
+        }
+        rec.stop(&mut data, &params);
+        assert_eq!(data, vec![2.0, 4.0, 6.0]);
+
+        // following passes mix into the buffer and wrap around at its end
+        rec.start(&params);
+        for _ in 0..4 {
+            rec.process_sample(1.0, &mut data);
+        }
+        rec.stop(&mut data, &params);
+        // first sample was overdubbed twice: (2.0 * 0.5 + 1.0) * 0.5 + 1.0
+        assert_eq!(data, vec![2.0, 3.0, 4.0]);
+        assert_eq!(rec.errors.incorrect_state, 0);
+
+        // writing starts at the transport position modulo the buffer length
+        rec.start(&params.with_transport_pos_samples(7));
+        rec.process_sample(2.0, &mut data);
+        rec.stop(&mut data, &params);
+        assert_eq!(data, vec![2.0, 3.5, 4.0]);
+    }
+
+    #[test]
+    fn test_recorder_overflow() {
+        let mut rec = Recorder::new(4);
+        let mut data = Vec::with_capacity(8);
+        let params = Params {
+            transport_pos_samples: 0.0,
+            fixed_size_samples: 0,
+            sample_id: 0,
+            samples_per_bar: 4.0,
+            recording_mode: RecordingMode::Free,
+            overdub_feedback: 1.0,
+        };
+
+        // take ends at the limit instead of growing the buffer
+        rec.start(&params);
+        for i in 1..10 {
+            rec.process_sample(i as f32, &mut data);
+        }
+        assert!(!rec.is_recording());
+        assert_eq!(data, vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(rec.overflow_count(), 1);
+        assert_eq!(data.capacity(), 8);
+
+        // buffer capacity bounds takes too
+        let mut rec = Recorder::new(100);
+        let mut data = Vec::with_capacity(3);
+        let capacity = data.capacity();
+        rec.start(&params);
+        for i in 0..10 {
+            rec.process_sample(i as f32, &mut data);
+        }
+        assert_eq!(data.len(), capacity);
+        assert_eq!(data.capacity(), capacity);
+        assert_eq!(rec.overflow_count(), 1);
+    }
 }
@@ -0,0 +1,95 @@
+// Picks the aux output a new voice plays on. Voices are mixed into the main output as well,
+// so hosts without aux outputs still hear everything.
+
+use crate::common_types::{Note, OutputRouting, Params, MAX_AUX_OUTPUTS};
+
+#[derive(Clone, Default, Debug)]
+pub struct Router {
+    // output the next round-robin voice goes to
+    next: usize,
+}
+
+impl Router {
+    /// `slice` is set for voices started from a slice point. None means the voice is only
+    /// in the main output.
+    pub fn route(&self, slice: Option<usize>, note: Note, params: &Params) -> Option<usize> {
+        let count = params.output_count.min(MAX_AUX_OUTPUTS);
+        if count == 0 {
+            return None;
+        }
+        match params.output_routing {
+            OutputRouting::Off => None,
+            OutputRouting::Slice => slice.map(|slice| slice % count),
+            OutputRouting::NoteRange => {
+                let offset = note.note.checked_sub(params.output_base_note)? as usize;
+                Some(offset / params.notes_per_output.max(1) % count)
+            }
+            OutputRouting::RoundRobin => Some(self.next % count),
+        }
+    }
+
+    /// Moves round-robin on once a voice routed to `output` has actually started, so
+    /// ignored retriggers don't skip outputs
+    pub fn voice_started(&mut self, output: Option<usize>, params: &Params) {
+        if let (OutputRouting::RoundRobin, Some(output)) = (params.output_routing, output) {
+            self.next = output + 1;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.next = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params(output_routing: OutputRouting) -> Params {
+        Params {
+            output_routing,
+            output_count: 3,
+            ..Params::default()
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let mut router = Router::default();
+        let note = |n: u8| Note::new(n, 0);
+
+        let p = params(OutputRouting::Off);
+        assert_eq!(router.route(Some(1), note(13), &p), None);
+
+        let p = params(OutputRouting::Slice);
+        assert_eq!(router.route(Some(1), note(13), &p), Some(1));
+        assert_eq!(router.route(Some(4), note(16), &p), Some(1));
+        assert_eq!(router.route(None, note(60), &p), None);
+
+        // ranges of 4 notes from note 12
+        let p = params(OutputRouting::NoteRange);
+        assert_eq!(router.route(None, note(11), &p), None);
+        assert_eq!(router.route(None, note(15), &p), Some(0));
+        assert_eq!(router.route(None, note(16), &p), Some(1));
+        assert_eq!(router.route(None, note(24), &p), Some(0));
+
+        let p = params(OutputRouting::RoundRobin);
+        let outputs: Vec<_> = (0..4)
+            .map(|_| {
+                let output = router.route(None, note(12), &p);
+                router.voice_started(output, &p);
+                output
+            })
+            .collect();
+        assert_eq!(outputs, vec![Some(0), Some(1), Some(2), Some(0)]);
+        // only started voices move it on
+        assert_eq!(router.route(None, note(12), &p), Some(1));
+        assert_eq!(router.route(None, note(12), &p), Some(1));
+
+        let p = Params {
+            output_count: 0,
+            ..p
+        };
+        assert_eq!(router.route(None, note(12), &p), None);
+    }
+}
@@ -1,22 +1,71 @@
 use std::fmt::Debug;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
 
+use smallvec::SmallVec;
+
+use crate::audio_file;
+use crate::capture::CaptureBuffer;
 use crate::clip;
 use crate::clip::Clip;
 pub use crate::common_types::LoopMode;
-use crate::common_types::{InitParams, Note, Params};
+use crate::common_types::{
+    Command, InitParams, Note, Params, PlaybackMode, RecordingMode, Retrigger, VoiceStealing,
+    WavFormat, MAX_AUX_OUTPUTS, MAX_VOICES, MAX_VOICE_STORAGE,
+};
+use crate::envelope::Envelope;
+use crate::grain::Granulator;
+use crate::interpolation;
 use crate::recorder::Recorder;
+use crate::routing::Router;
+use crate::shared::{copy_buffer, Buffer, SharedSlot, Slices, Spare, MAX_SLICES};
+use crate::snapshot;
+use crate::snapshot::SlotSnapshot;
+use crate::time_value::{calc_samples_since_bar, TimeValue};
 use crate::voice::Voice;
 use crate::volume::Volume;
+use crate::wav;
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct Channel {
-    pub(crate) data: Vec<f32>,
+    // only written while nothing else holds them, see `Sampler::make_writable`
+    pub(crate) slots: Vec<Buffer>,
+    pub(crate) recording_slot: usize,
     pub(crate) voices: Vec<Voice>,
     pub(crate) now: usize,
     pub(crate) passthru_on: bool,
     pub(crate) passthru_volume: Volume,
     pub(crate) recorder: Recorder,
+    pub(crate) capture: CaptureBuffer,
     pub(crate) next_voice_id: usize,
+    // a take ended since the slot version was last bumped
+    pub(crate) take_finished: bool,
+    // captures that got less than their length, the capture buffer did not reach back far enough
+    pub(crate) capture_truncations: usize,
+}
+
+// a derived clone would share the slot buffers and drop the room voices have
+impl Clone for Channel {
+    fn clone(&self) -> Self {
+        Self {
+            slots: self.slots.iter().map(copy_buffer).collect(),
+            recording_slot: self.recording_slot,
+            voices: {
+                let mut voices = Vec::with_capacity(MAX_VOICE_STORAGE);
+                voices.extend(self.voices.iter().cloned());
+                voices
+            },
+            now: self.now,
+            passthru_on: self.passthru_on,
+            passthru_volume: self.passthru_volume.clone(),
+            recorder: self.recorder.clone(),
+            capture: self.capture.clone(),
+            next_voice_id: self.next_voice_id,
+            take_finished: self.take_finished,
+            capture_truncations: self.capture_truncations,
+        }
+    }
 }
 
 #[derive(Clone, Default, Debug)]
@@ -26,29 +75,42 @@ pub struct VoiceInfo {
     pub pos: f32,
 }
 
+// length of the fade out applied to stolen voices
+const STEAL_FADE_SECONDS: f32 = 0.005;
+
 fn starting_offset(loop_start_percent: f32, data_len: usize) -> f32 {
     let len_f32 = data_len as f32;
     loop_start_percent * len_f32
 }
 
 impl Channel {
+    // slots are emptied by the sampler, which retires their buffers
     fn reset(&mut self) {
-        self.data.clear();
+        self.recording_slot = 0;
         self.voices.clear();
         self.now = 0;
         self.passthru_on = false;
         self.passthru_volume = Volume::new(0.0);
-        self.recorder = Recorder::new();
+        self.recorder = Recorder::new(self.recorder.max_samples());
+        self.capture.clear();
+        self.take_finished = false;
+        self.capture_truncations = 0;
     }
     fn new(params: &InitParams) -> Self {
         Channel {
-            data: vec![],
-            voices: vec![],
+            slots: (0..params.slot_count.max(1))
+                .map(|_| Buffer::default())
+                .collect(),
+            recording_slot: 0,
+            voices: Vec::with_capacity(MAX_VOICE_STORAGE),
             now: 0,
             passthru_on: false,
             passthru_volume: Volume::new(if params.auto_passthru { 1.0 } else { 0.0 }),
-            recorder: Recorder::new(),
+            recorder: Recorder::new(params.max_recording_samples),
+            capture: CaptureBuffer::new(params.capture_buffer_samples),
             next_voice_id: 0,
+            take_finished: false,
+            capture_truncations: 0,
         }
     }
 
@@ -56,23 +118,113 @@ impl Channel {
         &self.recorder
     }
 
+    fn slot_index(&self, params: &Params) -> usize {
+        params.sample_id.min(self.slots.len() - 1)
+    }
+
+    fn slice_start_percent(&self, slice: usize, onsets: &[usize], params: &Params) -> f32 {
+        let data_len = self.slots[self.slot_index(params)].len();
+        match onsets.get(slice) {
+            Some(&onset) if data_len > 0 => onset as f32 / data_len as f32,
+            // slices past the last onset follow the grid
+            _ => (slice % params.slice_count(data_len)) as f32 * params.slice_ratio(data_len),
+        }
+    }
+
     fn finish_voice(&mut self, now: usize, index: usize, params: &Params) {
-        let voice = &mut self.voices[index];
+        Self::finish(&mut self.voices[index], now, params);
+    }
+
+    fn finish(voice: &mut Voice, now: usize, params: &Params) {
         assert!(!voice.finished);
         //eprintln!("now={} stop playing voice={:?}", self.now, voice);
-        voice.volume.to(now, params.decay_samples, 0.0);
+        voice.envelope.release(now, params.release_samples);
         voice.finished_at = now;
         voice.finished = true;
     }
 
+    fn pick_voice_to_steal(&self, note: Note, params: &Params) -> Option<usize> {
+        let candidates = self.voices.iter().enumerate().filter(|(_, v)| !v.stolen);
+        let oldest = || candidates.clone().min_by_key(|(_, v)| v.id);
+        let picked = match params.voice_stealing {
+            VoiceStealing::Oldest => oldest(),
+            VoiceStealing::Quietest => candidates.clone().min_by(|(_, a), (_, b)| {
+                a.envelope
+                    .value(self.now)
+                    .total_cmp(&b.envelope.value(self.now))
+            }),
+            VoiceStealing::SameNote => candidates
+                .clone()
+                .filter(|(_, v)| v.note == note)
+                .min_by_key(|(_, v)| v.id)
+                .or_else(oldest),
+            VoiceStealing::LowestPriority => candidates
+                .clone()
+                .min_by_key(|(_, v)| (!v.finished, v.note.note, v.id)),
+        };
+        picked.map(|(i, _)| i)
+    }
+
+    fn steal_voice(&mut self, index: usize, params: &Params) {
+        let fade = (STEAL_FADE_SECONDS * params.transport.sample_rate) as usize;
+        let now = self.now;
+        let voice = &mut self.voices[index];
+        voice.envelope.release(now, fade);
+        if !voice.finished {
+            voice.finished = true;
+            voice.finished_at = now;
+        }
+        voice.stolen = true;
+    }
+
+    // makes room for one more voice if the limit has been reached
+    fn enforce_voice_limit(&mut self, note: Note, params: &Params) {
+        let max_voices = match params.max_voices {
+            0 => MAX_VOICES,
+            n => n.min(MAX_VOICES),
+        };
+        let count = self.voices.iter().filter(|v| !v.stolen).count();
+        if count < max_voices {
+            return;
+        }
+        for _ in 0..=(count - max_voices) {
+            if let Some(i) = self.pick_voice_to_steal(note, params) {
+                self.steal_voice(i, params);
+            }
+        }
+    }
+
+    // drops a voice right away when the preallocated storage is full, preferring ones
+    // that are already fading out. Only happens when more voices are stolen within the
+    // steal fade than there is headroom for.
+    fn make_room_for_voice(&mut self) {
+        if self.voices.len() < MAX_VOICE_STORAGE {
+            return;
+        }
+        let now = self.now;
+        if let Some(i) = self
+            .voices
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let key = |v: &Voice| (!v.stolen, !v.finished);
+                key(a)
+                    .cmp(&key(b))
+                    .then(a.envelope.value(now).total_cmp(&b.envelope.value(now)))
+                    .then(a.id.cmp(&b.id))
+            })
+            .map(|(i, _)| i)
+        {
+            self.voices.remove(i);
+        }
+    }
+
     pub fn set_note_speed(&mut self, note: Note, speed: f32) {
         for v in &mut self.voices {
             if v.note == note {
                 v.speed = speed;
-                // Not returning here as there may be multiple voices with the same note.
-                // This is a bug; voice with same note should probably reset the state of
-                // existing one and not create new voice.
-                // return;
+                // Not returning here as with Retrigger::Layer there may be multiple voices with
+                // the same note, as well as released voices that are still fading out.
             }
         }
         //
@@ -85,50 +237,92 @@ impl Channel {
         // }
     }
 
+    /// Without `loop_start_percent` the voice plays the whole slot from its start,
+    /// regardless of `Params::loop_length`. Returns false when no voice was started.
     pub fn start_playing(
         &mut self,
-        loop_start_percent: f32,
+        loop_start_percent: Option<f32>,
         note: Note,
         velocity: f32,
+        reverse: bool,
+        output: Option<usize>,
         params: &Params,
-    ) {
-        if self.data.is_empty() {
-            return;
+    ) -> bool {
+        let slot = self.slot_index(params);
+        let data_len = self.slots[slot].len();
+        if data_len == 0 {
+            return false;
         }
 
+        let whole = loop_start_percent.is_none();
+        let loop_start_percent = loop_start_percent.unwrap_or(0.0);
         assert!(loop_start_percent >= 0.0 && loop_start_percent <= 1.0);
-        let offset = starting_offset(loop_start_percent, self.data.len());
-        let length = params.loop_length(self.data.len());
+        let offset = starting_offset(loop_start_percent, data_len);
+        let length = if whole {
+            data_len as f32
+        } else {
+            params.loop_length(data_len)
+        };
         let clip2 = Clip::new(
             self.now,
             offset,
-            params.speed(),
+            if reverse {
+                -params.speed()
+            } else {
+                params.speed()
+            },
             length,
-            self.data.len() as clip::T,
+            data_len as clip::T,
             match params.loop_mode {
                 LoopMode::Loop | LoopMode::PlayOnce => clip::Mode::Loop,
                 LoopMode::PingPong => clip::Mode::PingPong,
             },
         );
+        let playing = self
+            .voices
+            .iter()
+            .position(|v| v.note == note && !v.finished);
+        match (params.retrigger, playing) {
+            (Retrigger::Ignore, Some(_)) => return false,
+            (Retrigger::Restart, Some(_)) => (),
+            _ => self.enforce_voice_limit(note, params),
+        }
         let mut voice = Voice {
+            id: self.next_voice_id,
             note: note,
+            slot,
             loop_start_percent,
             played: 0.0,
             clip2,
-            volume: Volume::new(0.0),
+            envelope: Envelope::new(self.now, velocity, params),
+            granulator: Granulator::new(),
             finished: false,
             is_at_zero_crossing: false,
             finished_at: 0,
+            stolen: false,
             last_sample_index: 0,
             last_sample_value: 0.0,
             speed: 1.0,
+            output,
+            reversed: reverse,
+            whole,
         };
         self.next_voice_id += 1;
-        voice.volume.to(self.now, params.attack_samples, velocity);
         // #[cfg(debug_assertions)]
         // nih_warn!("start_playing: voice={:?}", voice);
-        self.voices.push(voice);
+        match (params.retrigger, playing) {
+            (Retrigger::Restart, Some(i)) => {
+                voice.envelope = self.voices[i].envelope.clone();
+                voice.envelope.retrigger(self.now, velocity, params);
+                self.voices[i] = voice;
+            }
+            _ => {
+                self.make_room_for_voice();
+                self.voices.push(voice)
+            }
+        }
         self.handle_passthru(params);
+        true
     }
 
     pub fn stop_playing(&mut self, note: Note, params: &Params) {
@@ -144,50 +338,85 @@ impl Channel {
         }
     }
 
-    pub fn start_recording(&mut self) {
-        self.recorder.start();
+    pub fn start_recording(&mut self, params: &Params) {
+        if !self.recorder.is_recording() && !self.recorder.is_scheduled() {
+            self.recording_slot = self.slot_index(params);
+        }
+        self.recorder.start(&params.into());
+    }
+
+    fn is_taking(&self) -> bool {
+        self.recorder.is_recording() || self.recorder.is_scheduled()
     }
 
     pub fn stop_recording(&mut self, params: &Params) {
-        self.recorder.stop(&mut self.data, &params.into());
+        let was_recording = self.recorder.is_recording();
+        let mut unused = vec![];
+        let data = Arc::get_mut(&mut self.slots[self.recording_slot]).unwrap_or(&mut unused);
+        self.recorder.stop(data, &params.into());
+        self.take_finished |= was_recording && !self.recorder.is_recording();
+    }
+
+    // the slot has been made writable by the sampler
+    fn commit_capture(&mut self, slot: usize, params: &Params) {
+        let Some(data) = Arc::get_mut(&mut self.slots[slot]) else {
+            return;
+        };
+        let length = params.capture_length.as_samples(&params.transport).round() as usize;
+        // bars are taken from the host grid, ending on the last bar line rather than now
+        let skip = match params.capture_length {
+            TimeValue::Bars(_) => calc_samples_since_bar(&params.transport),
+            _ => 0,
+        };
+        let copied = self
+            .capture
+            .copy_before(skip, length.min(data.capacity()), data);
+        if copied < length {
+            self.capture_truncations += 1;
+        }
     }
 
     fn handle_passthru(&mut self, params: &Params) {
+        let have_unfinished_voices = self.voices.iter().any(|v| !v.finished);
+        self.update_passthru(self.now, have_unfinished_voices, params);
+    }
+
+    fn update_passthru(&mut self, now: usize, have_unfinished_voices: bool, params: &Params) {
         if params.auto_passthru {
-            let have_unfinished_voices = self.voices.iter().any(|v| !v.finished);
             if !have_unfinished_voices {
                 if !self.passthru_on {
                     self.passthru_on = true;
-                    self.passthru_volume
-                        .to(self.now, params.attack_samples, 1.0);
+                    self.passthru_volume.to(now, params.attack_samples, 1.0);
                 }
             } else {
                 if self.passthru_on {
                     self.passthru_on = false;
-                    self.passthru_volume.to(self.now, params.decay_samples, 0.0);
+                    self.passthru_volume.to(now, params.release_samples, 0.0);
                 }
             }
         } else {
             if self.passthru_on {
                 self.passthru_on = false;
-                self.passthru_volume.to(self.now, params.decay_samples, 0.0);
+                self.passthru_volume.to(now, params.release_samples, 0.0);
             }
         }
     }
 
     fn should_remove_voice(now: usize, voice: &Voice, params: &Params) -> bool {
-        if voice.finished {
+        if voice.stolen {
+            voice.envelope.is_off()
+        } else if voice.finished {
             match params.note_off_behavior {
                 crate::common_types::NoteOffBehaviour::ZeroCrossing => {
-                    if now - voice.finished_at >= params.decay_samples {
+                    if now - voice.finished_at >= params.release_samples {
                         return true;
                     }
                     voice.is_at_zero_crossing
                 }
-                crate::common_types::NoteOffBehaviour::Decay => voice.volume.is_static_and_mute(),
+                crate::common_types::NoteOffBehaviour::Decay => voice.envelope.is_off(),
                 crate::common_types::NoteOffBehaviour::DecayAndZeroCrossing => {
-                    if !voice.volume.is_static_and_mute() {
-                        if now - voice.finished_at >= params.decay_samples {
+                    if !voice.envelope.is_off() {
+                        if now - voice.finished_at >= params.release_samples {
                             return true;
                         }
                         voice.is_at_zero_crossing
@@ -201,79 +430,237 @@ impl Channel {
         }
     }
 
-    fn play_voices(&mut self, params: &Params) -> f32 {
+    // speed the clip advances at and, in granular mode, the rate grains are read at
+    fn voice_rates(voice: &Voice, params: &Params) -> (f32, f32) {
+        // in granular mode per note speed changes pitch only
+        match params.playback_mode {
+            PlaybackMode::Resample => (
+                voice.speed * params.speed() * voice.direction() * params.pitch,
+                0.0,
+            ),
+            PlaybackMode::Granular => (
+                params.speed() * voice.direction(),
+                voice.speed * params.pitch * params.reverse_speed.signum() * voice.direction(),
+            ),
+        }
+    }
+
+    fn update_clip(voice: &mut Voice, now: usize, data_len: usize, speed: f32, params: &Params) {
+        voice
+            .clip2
+            .update_length(now, voice.loop_length(data_len, params) as clip::T);
+        voice.clip2.update_speed(now, speed);
+        voice.clip2.update_data_length(now, data_len as clip::T);
+        voice.clip2.update_mode(
+            now,
+            match params.loop_mode {
+                LoopMode::Loop | LoopMode::PlayOnce => clip::Mode::Loop,
+                LoopMode::PingPong => clip::Mode::PingPong,
+            },
+        );
+    }
+
+    fn render_voice(
+        voice: &mut Voice,
+        data: &[f32],
+        now: usize,
+        (voice_speed, grain_rate): (f32, f32),
+        params: &Params,
+    ) -> f32 {
+        let offset = voice.clip2.offset(now);
+        let index = offset.floor() as usize;
+
+        let value = match params.playback_mode {
+            PlaybackMode::Resample => {
+                let value = interpolation::read(data, offset, params.interpolation);
+                let crossfade = match params.loop_mode {
+                    LoopMode::PlayOnce => None,
+                    _ => voice
+                        .clip2
+                        .crossfade(now, params.loop_crossfade_samples as clip::T),
+                };
+                match crossfade {
+                    Some((other, weight)) => {
+                        let other = interpolation::read(data, other, params.interpolation);
+                        value * (1.0 - weight) + other * weight
+                    }
+                    None => value,
+                }
+            }
+            PlaybackMode::Granular => voice.granulator.process(
+                data,
+                offset,
+                grain_rate,
+                params.grain_samples,
+                params.interpolation,
+            ),
+        } * voice.envelope.value(now);
+
+        voice.played += voice_speed;
+        voice.is_at_zero_crossing =
+            value.signum() != voice.last_sample_value.signum() || value == 0.0;
+        voice.last_sample_index = index;
+        voice.last_sample_value = value;
+
+        if !voice.finished
+            && params.loop_mode == LoopMode::PlayOnce
+            && voice.played.abs() >= voice.loop_length(data.len(), params).floor()
+        {
+            Self::finish(voice, now, params);
+        }
+        value
+    }
+
+    // runs for every sample, must not allocate. adds each voice to its entry in aux as well
+    fn play_voices(&mut self, aux: &mut [f32], params: &Params) -> f32 {
         let mut output = 0.0;
-        let mut finished: Vec<usize> = vec![];
-        for (i, voice) in self.voices.iter_mut().enumerate() {
+        for voice in self.voices.iter_mut() {
             // prevents voice playing 1 unnecessary
             // sample at the end when voice is cancelled by note and does not have any decay time
             if Self::should_remove_voice(self.now, voice, params) {
                 continue;
             }
-            let voice_speed = voice.speed * params.speed();
-
-            voice
-                .clip2
-                .update_length(self.now, params.loop_length(self.data.len()) as clip::T);
-            voice.clip2.update_speed(self.now, voice_speed);
-            voice
-                .clip2
-                .update_data_length(self.now, self.data.len() as clip::T);
-            voice.clip2.update_mode(
-                self.now,
-                match params.loop_mode {
-                    LoopMode::Loop | LoopMode::PlayOnce => clip::Mode::Loop,
-                    LoopMode::PingPong => clip::Mode::PingPong,
-                },
-            );
-            let index = voice.clip2.offset(self.now).floor() as usize;
+            let data = &self.slots[voice.slot];
+            if data.is_empty() {
+                continue;
+            }
+            let rates = Self::voice_rates(voice, params);
+            Self::update_clip(voice, self.now, data.len(), rates.0, params);
+            let value = Self::render_voice(voice, data, self.now, rates, params);
+            if let Some(out) = voice.output.and_then(|i| aux.get_mut(i)) {
+                *out += value;
+            }
+            output += value;
+        }
 
-            let value = self.data[index] * voice.volume.value(self.now);
+        // update voice volumes and remove voices that are finished and mute
+        let now = self.now;
+        self.voices.retain_mut(|voice| {
+            voice.envelope.step(now);
+            // #[cfg(debug_assertions)]
+            // nih_warn!("removing: voice={:?}", voice);
+            !Self::should_remove_voice(now, voice, params)
+        });
+        output
+    }
 
-            output += value;
-            voice.played += voice_speed;
-            voice.is_at_zero_crossing =
-                value.signum() != voice.last_sample_value.signum() || value == 0.0;
-            voice.last_sample_index = index;
-            voice.last_sample_value = value;
-
-            if !voice.finished
-                && params.loop_mode == LoopMode::PlayOnce
-                && voice.played.abs() >= params.loop_length(self.data.len()).floor()
-            {
-                finished.push(i);
+    // renders a voice over a run of samples without commands or recording, adding to
+    // output and the voice's aux output, returns true if it can be removed
+    fn play_voice_block(
+        voice: &mut Voice,
+        data: &[f32],
+        start: usize,
+        output: &mut [f32],
+        mut aux_output: Option<&mut [f32]>,
+        params: &Params,
+    ) -> bool {
+        // nothing the clip depends on changes until the next command
+        let rates = Self::voice_rates(voice, params);
+        if !data.is_empty() {
+            Self::update_clip(voice, start, data.len(), rates.0, params);
+        }
+        for (k, out) in output.iter_mut().enumerate() {
+            let now = start + k;
+            if !data.is_empty() && !Self::should_remove_voice(now, voice, params) {
+                let value = Self::render_voice(voice, data, now, rates, params);
+                if let Some(aux_output) = aux_output.as_deref_mut() {
+                    aux_output[k] += value;
+                }
+                *out += value;
+            }
+            voice.envelope.step(now);
+            if Self::should_remove_voice(now, voice, params) {
+                return true;
             }
         }
+        false
+    }
 
-        // remove voices that are finished and mute
-        while let Some(j) = finished.pop() {
-            self.finish_voice(self.now, j, params);
+    /// Same as calling `process_sample_with_record_input` for each sample, but one voice at
+    /// a time. Only valid while nothing is being recorded as playback may read the recorded
+    /// buffer. `aux_outputs` hold this channel's run of samples of every aux output, which
+    /// must have been cleared.
+    fn process_block(
+        &mut self,
+        input: &[f32],
+        record_input: &[f32],
+        output: &mut [f32],
+        aux_outputs: &mut [&mut [f32]],
+        params: &Params,
+    ) {
+        debug_assert!(!self.recorder.is_recording() && !self.recorder.is_scheduled());
+        let start = self.now;
+        for &x in record_input {
+            self.capture.push(x);
         }
+        output.fill(0.0);
 
-        // update voice volumes and find voices that can be removed (finished and mute)
-        let mut removed = vec![];
-        for (i, voice) in self.voices.iter_mut().enumerate() {
-            voice.volume.step(self.now);
-            if Self::should_remove_voice(self.now, voice, params) {
-                removed.push(i);
+        // passthru follows whether any voice was unfinished at each sample, voices
+        // finish at most once in a block
+        let mut unfinished_until = 0;
+        let mut i = 0;
+        while i < self.voices.len() {
+            let voice = &mut self.voices[i];
+            let aux_output = voice
+                .output
+                .and_then(|i| aux_outputs.get_mut(i))
+                .map(|aux_output| &mut **aux_output);
+            let removed = Self::play_voice_block(
+                voice,
+                &self.slots[voice.slot],
+                start,
+                output,
+                aux_output,
+                params,
+            );
+            let until = if voice.finished {
+                voice.finished_at
+            } else {
+                start + output.len()
+            };
+            unfinished_until = unfinished_until.max(until);
+            if removed {
+                self.voices.remove(i);
+            } else {
+                i += 1;
             }
         }
 
-        // remove voices that are finished and mute
-        while let Some(j) = removed.pop() {
-            // #[cfg(debug_assertions)]
-            // nih_warn!("removing: voice={:?}", self.voices[j]);
-            self.voices.remove(j);
+        for (k, (out, &x)) in output.iter_mut().zip(input).enumerate() {
+            let now = start + k;
+            *out += x * self.passthru_volume.value(now);
+            self.passthru_volume.step(now);
+            self.update_passthru(now, unfinished_until > now, params);
         }
-        output
+        self.now = start + output.len();
     }
 
     pub fn process_sample<'a>(&mut self, input: f32, params: &Params) -> f32 {
-        self.recorder.process_sample(input, &mut self.data);
+        self.process_sample_with_record_input(input, input, &mut [], params)
+    }
+
+    /// `record_input` goes to the recorder and capture buffer, `input` is passed through.
+    /// Voices routed to an aux output are added to its entry in `aux`.
+    pub fn process_sample_with_record_input(
+        &mut self,
+        input: f32,
+        record_input: f32,
+        aux: &mut [f32],
+        params: &Params,
+    ) -> f32 {
+        if self.is_taking() {
+            let was_recording = self.recorder.is_recording();
+            if let Some(data) = Arc::get_mut(&mut self.slots[self.recording_slot]) {
+                self.recorder.process_sample(record_input, data);
+            }
+            // fixed length takes stop by themselves
+            self.take_finished |= was_recording && !self.recorder.is_recording();
+        }
+        self.capture.push(record_input);
 
         let mut output = 0.0;
-        if !self.data.is_empty() {
-            output += self.play_voices(params);
+        if !self.voices.is_empty() {
+            output += self.play_voices(aux, params);
         }
 
         // passthru handling
@@ -303,6 +690,26 @@ impl Channel {
 #[derive(Clone, Debug)]
 pub struct Sampler {
     pub channels: Vec<Channel>,
+    // changes whenever slot contents are replaced
+    data_version: usize,
+    // the same per slot
+    versions: Vec<usize>,
+    // detected onsets per slot, shared by all channels so they stay in sync
+    slices: Vec<Slices>,
+    // version of the slot the onsets were detected on, other versions fall back to the grid
+    slices_versions: Vec<usize>,
+    // rate each slot was recorded or loaded at
+    sample_rates: Vec<f32>,
+    router: Router,
+    // what the next take or capture is written into when its slot can't be written in place
+    spare: Option<Spare>,
+    // buffers the sampler no longer uses, to be dropped off the audio thread
+    retired: Vec<Buffer>,
+    max_recording_samples: usize,
+    // takes and captures that found no buffer to write into
+    dropped_takes: usize,
+    // what emptied slots hold, so emptying them doesn't allocate
+    empty: Buffer,
 }
 
 #[derive(Default, Clone, Debug)]
@@ -317,27 +724,53 @@ impl Sampler {
         self.channels.iter_mut().for_each(|ch| {
             ch.reset();
         });
+        for slot in 0..self.get_slot_count() {
+            self.retire_slot(slot);
+        }
+        self.router.reset();
     }
     pub fn print_error_info(&self, channel: usize) -> String {
         self.channels[channel].recorder().print_error_info()
     }
+    /// Number of takes that ended early because they reached the maximum recording length
+    pub fn recording_overflow_count(&self, channel: usize) -> usize {
+        self.channels[channel].recorder().overflow_count()
+    }
+    /// Number of captures that were shorter than the capture length because the capture buffer
+    /// did not hold enough input
+    pub fn capture_truncation_count(&self, channel: usize) -> usize {
+        self.channels[channel].capture_truncations
+    }
+    /// Number of takes and captures that were dropped because their slot was in use
+    /// elsewhere and no spare buffer was ready, see `set_spare`. Hosts that don't call
+    /// `update_buffers` in between blocks run out of spares after the first take.
+    pub fn dropped_take_count(&self) -> usize {
+        self.dropped_takes
+    }
+    /// Notes of voices that have not been released yet, each note reported once even if
+    /// several voices are layered on it
     pub fn iter_active_notes(&self, channel: usize) -> impl Iterator<Item = Note> + '_ {
-        self.channels[channel].voices.iter().filter_map(|v| {
-            if !v.finished {
+        let voices = &self.channels[channel].voices;
+        voices.iter().enumerate().filter_map(|(i, v)| {
+            let seen = voices[..i].iter().any(|u| !u.finished && u.note == v.note);
+            if !v.finished && !seen {
                 Some(v.note)
             } else {
                 None
             }
         })
     }
-    pub fn get_waveform_summary(&self, resolution: usize) -> WaveformSummary {
-        let data = &self.channels[0].data;
+    pub fn get_waveform_summary(&self, slot: usize, resolution: usize) -> WaveformSummary {
+        let data = &self.channels[0].slots[slot];
         let step = data.len() as f32 / resolution as f32;
         let mut r = WaveformSummary {
             data: vec![0.0; resolution],
             min: 0.0,
             max: 0.0,
         };
+        if data.is_empty() {
+            return r;
+        }
         for i in 0..resolution {
             let a = ((i as f32) * step).floor() as usize;
             let b = (((i + 1) as f32) * step).floor() as usize;
@@ -351,8 +784,21 @@ impl Sampler {
     }
 
     pub fn new(channel_count: usize, params: &InitParams) -> Self {
+        let slot_count = params.slot_count.max(1);
         Self {
             channels: vec![Channel::new(params); channel_count],
+            data_version: 0,
+            versions: vec![0; slot_count],
+            slices: vec![Slices::new(); slot_count],
+            slices_versions: vec![0; slot_count],
+            sample_rates: vec![params.sample_rate; slot_count],
+            router: Router::default(),
+            spare: Some(Spare::empty(channel_count, params.max_recording_samples)),
+            // room for a reset and a few spares between two drains
+            retired: Vec::with_capacity((slot_count + 4) * channel_count),
+            max_recording_samples: params.max_recording_samples,
+            dropped_takes: 0,
+            empty: Buffer::default(),
         }
     }
     fn each<F>(&mut self, f: F)
@@ -366,13 +812,321 @@ impl Sampler {
         self.each(|ch| ch.set_note_speed(note, speed))
     }
 
-    pub fn start_playing(&mut self, pos: f32, note: Note, velocity: f32, params: &Params) {
+    /// `reverse` plays the voice backwards, independently of `Params::reverse_speed`
+    pub fn start_playing(
+        &mut self,
+        pos: f32,
+        note: Note,
+        velocity: f32,
+        reverse: bool,
+        params: &Params,
+    ) {
         #[cfg(debug_assertions)]
         eprintln!(
-            "sampler: start_playing({}, {:?}, {}, {:?})",
-            pos, note, velocity, params
+            "sampler: start_playing({}, {:?}, {}, {}, {:?})",
+            pos, note, velocity, reverse, params
         );
-        self.each(|ch| ch.start_playing(pos, note, velocity, params));
+        let output = self.router.route(None, note, params);
+        let mut started = false;
+        self.each(|ch| {
+            started |= ch.start_playing(Some(pos), note, velocity, reverse, output, params)
+        });
+        if started {
+            self.router.voice_started(output, params);
+        }
+    }
+
+    /// Plays the whole slot from the start, regardless of `Params::loop_length`
+    pub fn start_playing_whole(
+        &mut self,
+        note: Note,
+        velocity: f32,
+        reverse: bool,
+        params: &Params,
+    ) {
+        let output = self.router.route(None, note, params);
+        let mut started = false;
+        self.each(|ch| started |= ch.start_playing(None, note, velocity, reverse, output, params));
+        if started {
+            self.router.voice_started(output, params);
+        }
+    }
+
+    /// Starts playing from a slice point, detected onsets are used when the slot has
+    /// them and the grid set by `Params::slice_length` otherwise
+    pub fn start_playing_slice(
+        &mut self,
+        slice: usize,
+        note: Note,
+        velocity: f32,
+        reverse: bool,
+        params: &Params,
+    ) {
+        let output = self.router.route(Some(slice), note, params);
+        let slot = self.selected_slot(params);
+        let onsets = if self.slices_versions[slot] == self.versions[slot] {
+            &self.slices[slot][..]
+        } else {
+            &[]
+        };
+        let mut started = false;
+        for ch in self.channels.iter_mut() {
+            let pos = ch.slice_start_percent(slice, onsets, params);
+            started |= ch.start_playing(Some(pos), note, velocity, reverse, output, params);
+        }
+        if started {
+            self.router.voice_started(output, params);
+        }
+    }
+
+    fn selected_slot(&self, params: &Params) -> usize {
+        params.sample_id.min(self.versions.len() - 1)
+    }
+
+    fn is_taking(&self, slot: usize) -> bool {
+        self.channels
+            .iter()
+            .any(|ch| ch.is_taking() && ch.recording_slot == slot)
+    }
+
+    fn changed(&mut self, slot: usize) {
+        self.versions[slot] += 1;
+        self.data_version += 1;
+    }
+
+    pub fn get_data_version(&self) -> usize {
+        self.data_version
+    }
+
+    /// Detected onsets of a slot, empty while they are being detected or when slices follow
+    /// the grid
+    pub fn get_slices(&self, slot: usize) -> &[usize] {
+        if self.slices_versions[slot] == self.versions[slot] {
+            &self.slices[slot]
+        } else {
+            &[]
+        }
+    }
+
+    /// First slot whose contents changed since its onsets were detected, leaving out slots
+    /// that are being recorded into. Detection is up to the host, see `SharedSlot::detect_slices`.
+    pub fn stale_slices(&self) -> Option<usize> {
+        (0..self.versions.len()).find(|&slot| {
+            self.slices_versions[slot] != self.versions[slot] && !self.is_taking(slot)
+        })
+    }
+
+    /// Sets the onsets detected on `version` of a slot, past `MAX_SLICES` they are dropped.
+    /// Onsets of an older version are ignored.
+    pub fn set_slices(&mut self, slot: usize, version: usize, onsets: &[usize]) -> bool {
+        if version != self.versions[slot] {
+            return false;
+        }
+        self.slices[slot].clear();
+        self.slices[slot].extend_from_slice(&onsets[..onsets.len().min(MAX_SLICES)]);
+        self.slices_versions[slot] = version;
+        self.data_version += 1;
+        true
+    }
+
+    /// Version of a slot's contents, changes whenever they are replaced
+    pub fn get_slot_version(&self, slot: usize) -> usize {
+        self.versions[slot]
+    }
+
+    // empties a slot without dropping its buffers here
+    fn retire_slot(&mut self, slot: usize) {
+        for ch in self.channels.iter_mut() {
+            if !Arc::ptr_eq(&ch.slots[slot], &self.empty) {
+                let old = std::mem::replace(&mut ch.slots[slot], self.empty.clone());
+                self.retired.push(old);
+            }
+        }
+        self.changed(slot);
+    }
+
+    // gives every channel a buffer for `slot` that nothing else holds, with room for the
+    // longest take. the spare stands in when the slot's own buffers are shared or too small.
+    // when the contents have to be kept only a copy of them as they are now will do, a stale
+    // spare is left for later and the take is dropped rather than starting from nothing
+    fn make_writable(&mut self, slot: usize, keep_contents: bool) -> bool {
+        let capacity = self.max_recording_samples;
+        if self.channels.iter_mut().all(|ch| {
+            Arc::get_mut(&mut ch.slots[slot]).is_some_and(|data| data.capacity() >= capacity)
+        }) {
+            return true;
+        }
+        let current = self.spare_is_current(slot);
+        if keep_contents && !current {
+            self.dropped_takes += 1;
+            return false;
+        }
+        let Some(spare) = self.spare.take() else {
+            self.dropped_takes += 1;
+            return false;
+        };
+        for (ch, mut buffer) in self.channels.iter_mut().zip(spare.channels) {
+            if let Some(data) = Arc::get_mut(&mut buffer) {
+                if !current {
+                    data.clear();
+                }
+            }
+            let old = std::mem::replace(&mut ch.slots[slot], buffer);
+            self.retired.push(old);
+        }
+        self.changed(slot);
+        true
+    }
+
+    /// Another handle on a slot's buffers for reading them off the audio thread, which
+    /// doesn't allocate. None while a take is writing into the slot.
+    pub fn share_slot(&self, slot: usize) -> Option<SharedSlot> {
+        if self.is_taking(slot) {
+            return None;
+        }
+        Some(self.shared(slot))
+    }
+
+    fn shared(&self, slot: usize) -> SharedSlot {
+        SharedSlot {
+            version: self.versions[slot],
+            sample_rate: self.sample_rates[slot],
+            channels: self
+                .channels
+                .iter()
+                .map(|ch| ch.slots[slot].clone())
+                .collect(),
+            slices: self.get_slices(slot).iter().copied().collect(),
+        }
+    }
+
+    /// Swaps buffers prepared elsewhere into a slot, one per channel, and retires the ones
+    /// it had. The slot's onsets are taken from `data` as they are. Slots that are being
+    /// recorded into are left as they are and the new buffers are retired instead.
+    pub fn replace_slot(&mut self, slot: usize, data: SharedSlot) -> bool {
+        if self.is_taking(slot) || data.channels.len() != self.channels.len() {
+            self.retired.extend(data.channels);
+            return false;
+        }
+        for (ch, buffer) in self.channels.iter_mut().zip(data.channels) {
+            let old = std::mem::replace(&mut ch.slots[slot], buffer);
+            self.retired.push(old);
+        }
+        self.sample_rates[slot] = data.sample_rate;
+        self.changed(slot);
+        self.slices[slot] = data.slices;
+        self.slices_versions[slot] = self.versions[slot];
+        true
+    }
+
+    /// Whether the spare is a copy of the slot as it is now, so a take can keep its contents
+    pub fn spare_is_current(&self, slot: usize) -> bool {
+        self.spare
+            .as_ref()
+            .is_some_and(|spare| spare.slot == slot && spare.version == self.versions[slot])
+    }
+
+    pub fn has_spare(&self) -> bool {
+        self.spare.is_some()
+    }
+
+    /// Hands the sampler the buffers the next take or capture goes into. The previous spare
+    /// is retired, as is one made for a different channel count.
+    pub fn set_spare(&mut self, spare: Spare) {
+        if let Some(previous) = self.spare.take() {
+            self.retired.extend(previous.channels);
+        }
+        if spare.channels.len() == self.channels.len() {
+            self.spare = Some(spare);
+        } else {
+            self.retired.extend(spare.channels);
+        }
+    }
+
+    /// Buffers the sampler no longer uses. They should be dropped off the audio thread, as
+    /// that frees them.
+    pub fn drain_retired(&mut self) -> std::vec::Drain<'_, Buffer> {
+        self.retired.drain(..)
+    }
+
+    /// Does what a host otherwise does off the audio thread: detects onsets of changed slots,
+    /// drops retired buffers and gives the sampler a spare for the selected slot. Allocates.
+    pub fn update_buffers(&mut self, params: &Params) {
+        while let Some(slot) = self.stale_slices() {
+            let Some(shared) = self.share_slot(slot) else {
+                break;
+            };
+            let onsets = shared.detect_slices(params.onset_sensitivity);
+            self.set_slices(slot, shared.version, &onsets);
+        }
+        let slot = self.selected_slot(params);
+        if !self.spare_is_current(slot) {
+            if let Some(source) = self.share_slot(slot) {
+                let spare = Spare::new(
+                    slot,
+                    &source,
+                    self.channels.len(),
+                    self.max_recording_samples,
+                );
+                self.set_spare(spare);
+            }
+        }
+        self.retired.clear();
+    }
+
+    /// Copies of non-empty slots. Slots that would take the total encoded size over
+    /// `max_bytes` are left out.
+    pub fn snapshot_slots(&self, max_bytes: usize) -> Vec<SlotSnapshot> {
+        let slots: Vec<SharedSlot> = (0..self.get_slot_count())
+            .map(|slot| self.shared(slot))
+            .collect();
+        snapshot::snapshot_slots(&slots, max_bytes)
+    }
+
+    /// Puts back slots saved by `snapshot_slots`, which should have been through
+    /// `snapshot::prepare` first as nothing is resampled here. Allocates.
+    pub fn restore_slots(&mut self, snapshots: Vec<SlotSnapshot>) {
+        for snapshot in snapshots {
+            if snapshot.slot >= self.get_slot_count() {
+                continue;
+            }
+            let slot = snapshot.slot;
+            let channels = audio_file::map_channels(snapshot.channels, self.channels.len());
+            let shared = SharedSlot::from_snapshot(SlotSnapshot {
+                channels,
+                ..snapshot
+            });
+            self.replace_slot(slot, shared);
+        }
+    }
+
+    /// Resamples slots recorded at another rate to `sample_rate` so they keep their pitch
+    /// and timing. Allocates, meant for when the host sample rate changes.
+    pub fn convert_slots(&mut self, sample_rate: f32) {
+        let channel_count = self.channels.len();
+        let snapshots: Vec<SlotSnapshot> = self
+            .snapshot_slots(usize::MAX)
+            .into_iter()
+            .filter(|s| s.sample_rate != sample_rate)
+            .map(|s| snapshot::prepare(s, channel_count, sample_rate))
+            .collect();
+        self.sample_rates.fill(sample_rate);
+        if !snapshots.is_empty() {
+            self.restore_slots(snapshots);
+        }
+    }
+
+    /// Rate the slot's data was recorded or loaded at
+    pub fn get_slot_sample_rate(&self, slot: usize) -> f32 {
+        self.sample_rates[slot]
+    }
+
+    // onsets of the take are detected off the audio thread, see `stale_slices`
+    fn update_version_if_take_finished(&mut self) {
+        if !self.channels.is_empty() && self.channels.iter().all(|ch| ch.take_finished) {
+            self.changed(self.channels[0].recording_slot);
+            self.each(|ch| ch.take_finished = false);
+        }
     }
 
     pub fn stop_playing(&mut self, note: Note, params: &Params) {
@@ -381,16 +1135,71 @@ impl Sampler {
         self.each(|ch| ch.stop_playing(note, params));
     }
 
-    pub fn start_recording(&mut self) {
-        self.each(|ch| Channel::start_recording(ch));
+    /// Returns false when the take is dropped because the selected slot can't be written to
+    /// without a spare, see `set_spare` and `dropped_take_count`. Overdubs also need the
+    /// spare to be a copy of the slot as it is now, otherwise the slot is left untouched.
+    pub fn start_recording(&mut self, params: &Params) -> bool {
+        // a take that is already running only gets the note
+        if !self.channels.iter().any(|ch| ch.is_taking()) {
+            let slot = self.selected_slot(params);
+            let overdub = params.recording_mode == RecordingMode::Overdub;
+            if !self.make_writable(slot, overdub) {
+                return false;
+            }
+            self.sample_rates[slot] = params.transport.sample_rate;
+        }
+        self.each(|ch| Channel::start_recording(ch, params));
+        true
     }
 
     pub fn stop_recording(&mut self, params: &Params) {
         self.each(|ch| Channel::stop_recording(ch, params));
+        self.update_version_if_take_finished();
+    }
+
+    /// Replaces the selected slot with the last `Params::capture_length` of input. Returns
+    /// false when nothing was captured, like a dropped take when there is no spare.
+    pub fn commit_capture(&mut self, params: &Params) -> bool {
+        let slot = self.selected_slot(params);
+        // recorder may be writing into the slot
+        let recording = self
+            .channels
+            .iter()
+            .any(|ch| ch.recorder.is_recording() && ch.recording_slot == slot);
+        if recording || !self.make_writable(slot, false) {
+            return false;
+        }
+        self.each(|ch| ch.commit_capture(slot, params));
+        self.sample_rates[slot] = params.transport.sample_rate;
+        self.changed(slot);
+        true
+    }
+
+    /// Replaces a slot with prepared data, one buffer per channel as returned by
+    /// `audio_file::load`, cut off at the maximum recording length. Slots that are being
+    /// recorded into are left as they are. Allocates, `replace_slot` is the version for the
+    /// audio thread.
+    pub fn load_slot(&mut self, slot: usize, data: Vec<Vec<f32>>, params: &Params) {
+        let shared = SharedSlot::new(
+            audio_file::map_channels(data, self.channels.len()),
+            params.transport.sample_rate,
+            self.max_recording_samples,
+            params.onset_sensitivity,
+        );
+        self.replace_slot(slot, shared);
+    }
+
+    /// Loads a WAV or AIFF file into the selected slot, resampled to the transport sample rate
+    pub fn load_file(&mut self, path: &Path, params: &Params) -> io::Result<()> {
+        let data = audio_file::load(path, self.channels.len(), params.transport.sample_rate)?;
+        self.load_slot(self.selected_slot(params), data, params);
+        Ok(())
     }
 
     pub fn process_sample<'a>(&mut self, channel: usize, input: f32, params: &Params) -> f32 {
-        params.volume * self.channels[channel].process_sample(input, params)
+        let output = params.volume * self.channels[channel].process_sample(input, params);
+        self.update_version_if_take_finished();
+        output
     }
 
     pub fn process_frame<'a>(&mut self, frame: &mut [&'a mut f32], params: &Params) {
@@ -399,6 +1208,203 @@ impl Sampler {
         }
     }
 
+    fn apply_command(&mut self, command: Command, params: &mut Params) {
+        match command {
+            Command::StartPlaying {
+                pos,
+                note,
+                velocity,
+                reverse,
+            } => self.start_playing(pos, note, velocity, reverse, params),
+            Command::StartPlayingSlice {
+                slice,
+                note,
+                velocity,
+                reverse,
+            } => self.start_playing_slice(slice, note, velocity, reverse, params),
+            Command::StartPlayingWhole {
+                note,
+                velocity,
+                reverse,
+            } => self.start_playing_whole(note, velocity, reverse, params),
+            Command::StopPlaying { note } => self.stop_playing(note, params),
+            // dropped takes are counted, see `dropped_take_count`
+            Command::StartRecording => {
+                self.start_recording(params);
+            }
+            Command::StopRecording => self.stop_recording(params),
+            Command::CommitCapture => {
+                self.commit_capture(params);
+            }
+            Command::SetNoteSpeed { note, speed } => self.set_note_speed(note, speed),
+            Command::SetSpeed { speed } => params.speed = speed,
+            Command::SetPitch { pitch } => params.pitch = pitch,
+            Command::SetReverse { reverse } => {
+                params.reverse_speed = if reverse { -1.0 } else { 1.0 }
+            }
+            Command::SelectSlot { slot } => params.sample_id = slot,
+        }
+    }
+
+    /// Processes a block of samples, applying each command just before the sample at its
+    /// offset. Events must be sorted by offset, ones past the end of the block are applied
+    /// after it. `params` are used for the whole block apart from what commands override.
+    /// Events are `(offset, command)` pairs or convert to them, so a caller's own event type
+    /// needn't be copied into a new list.
+    pub fn process_block<I: AsRef<[f32]>, O: AsMut<[f32]>, E: Copy + Into<(usize, Command)>>(
+        &mut self,
+        inputs: &[I],
+        outputs: &mut [O],
+        events: &[E],
+        params: &Params,
+    ) {
+        self.process_block_with_record_inputs(inputs, inputs, outputs, events, params)
+    }
+
+    /// Same as `process_block`, but records and captures `record_inputs` while `inputs`
+    /// are passed through, e.g. to sample a sidechain
+    pub fn process_block_with_record_inputs<
+        I: AsRef<[f32]>,
+        R: AsRef<[f32]>,
+        O: AsMut<[f32]>,
+        E: Copy + Into<(usize, Command)>,
+    >(
+        &mut self,
+        inputs: &[I],
+        record_inputs: &[R],
+        outputs: &mut [O],
+        events: &[E],
+        params: &Params,
+    ) {
+        let mut no_aux_outputs: [&mut [f32]; 0] = [];
+        self.process_block_with_aux_outputs(
+            inputs,
+            record_inputs,
+            outputs,
+            &mut no_aux_outputs,
+            events,
+            params,
+        )
+    }
+
+    /// Same as `process_block_with_record_inputs`, also writing voices routed to an aux
+    /// output into `aux_outputs`. These hold one buffer per channel for each aux output in
+    /// turn, i.e. channel `c` of output `o` is at `o * channel_count + c`. Voices stay in
+    /// the main outputs as well.
+    pub fn process_block_with_aux_outputs<
+        I: AsRef<[f32]>,
+        R: AsRef<[f32]>,
+        O: AsMut<[f32]>,
+        A: AsMut<[f32]>,
+        E: Copy + Into<(usize, Command)>,
+    >(
+        &mut self,
+        inputs: &[I],
+        record_inputs: &[R],
+        outputs: &mut [O],
+        aux_outputs: &mut [A],
+        events: &[E],
+        params: &Params,
+    ) {
+        let event = |&e: &E| -> (usize, Command) { e.into() };
+        debug_assert!(events.windows(2).all(|w| event(&w[0]).0 <= event(&w[1]).0));
+        let frames = inputs
+            .iter()
+            .map(|input| input.as_ref().len())
+            .chain(record_inputs.iter().map(|input| input.as_ref().len()))
+            .chain(outputs.iter_mut().map(|output| output.as_mut().len()))
+            .chain(aux_outputs.iter_mut().map(|output| output.as_mut().len()))
+            .min()
+            .unwrap_or(0);
+        let channel_count = self.channels.len().max(1);
+        let aux_count = (aux_outputs.len() / channel_count).min(MAX_AUX_OUTPUTS);
+        // voices are added to aux outputs, which only have what is routed to them
+        for aux_output in aux_outputs.iter_mut() {
+            aux_output.as_mut()[..frames].fill(0.0);
+        }
+        let mut params = params.clone();
+        let block_pos = params.transport.pos_samples;
+        let mut events = events.iter().map(event).peekable();
+        let mut start = 0;
+        loop {
+            while let Some(&(_, command)) = events.peek().filter(|(offset, _)| *offset <= start) {
+                // position of the sample the command applies at
+                params.transport.pos_samples = block_pos + start as f32;
+                self.apply_command(command, &mut params);
+                events.next();
+            }
+            if start >= frames {
+                break;
+            }
+            let end = events
+                .peek()
+                .map_or(frames, |(offset, _)| (*offset).min(frames));
+
+            let recording = self
+                .channels
+                .iter()
+                .any(|ch| ch.recorder.is_recording() || ch.recorder.is_scheduled());
+            if recording {
+                // the buffer changes under the voices, go sample by sample
+                for i in start..end {
+                    for (c, (ch, ((input, record_input), output))) in self
+                        .channels
+                        .iter_mut()
+                        .zip(inputs.iter().zip(record_inputs).zip(outputs.iter_mut()))
+                        .enumerate()
+                    {
+                        let mut aux = [0.0; MAX_AUX_OUTPUTS];
+                        output.as_mut()[i] = params.volume
+                            * ch.process_sample_with_record_input(
+                                input.as_ref()[i],
+                                record_input.as_ref()[i],
+                                &mut aux[..aux_count],
+                                &params,
+                            );
+                        for (o, value) in aux[..aux_count].iter().enumerate() {
+                            aux_outputs[o * channel_count + c].as_mut()[i] = params.volume * value;
+                        }
+                    }
+                    self.update_version_if_take_finished();
+                }
+            } else {
+                for (c, (ch, ((input, record_input), output))) in self
+                    .channels
+                    .iter_mut()
+                    .zip(inputs.iter().zip(record_inputs).zip(outputs.iter_mut()))
+                    .enumerate()
+                {
+                    let output = &mut output.as_mut()[start..end];
+                    let mut channel_aux_outputs: SmallVec<&mut [f32], MAX_AUX_OUTPUTS> =
+                        aux_outputs
+                            .iter_mut()
+                            .skip(c)
+                            .step_by(channel_count)
+                            .take(aux_count)
+                            .map(|aux_output| &mut aux_output.as_mut()[start..end])
+                            .collect();
+                    ch.process_block(
+                        &input.as_ref()[start..end],
+                        &record_input.as_ref()[start..end],
+                        output,
+                        &mut channel_aux_outputs,
+                        &params,
+                    );
+                    output.iter_mut().for_each(|x| *x *= params.volume);
+                    for aux_output in channel_aux_outputs.iter_mut() {
+                        aux_output.iter_mut().for_each(|x| *x *= params.volume);
+                    }
+                }
+                self.update_version_if_take_finished();
+            }
+            start = end;
+        }
+        // events past the end of the block
+        for (_, command) in events {
+            self.apply_command(command, &mut params);
+        }
+    }
+
     pub fn get_frames_processed(&self, channel: usize) -> usize {
         self.channels[channel].now
     }
@@ -415,31 +1421,60 @@ impl Sampler {
     }
 
     pub fn get_last_recorded_offsets(&self) -> Vec<Option<usize>> {
+        self.iter_last_recorded_offsets().collect()
+    }
+
+    pub fn iter_last_recorded_offsets(&self) -> impl Iterator<Item = Option<usize>> + '_ {
         self.channels
             .iter()
             .map(|x| x.recorder().last_recorded_offset())
-            .collect()
     }
 
-    pub fn get_data_len(&self, channel: usize) -> usize {
+    pub fn get_slot_count(&self) -> usize {
+        self.channels.first().map_or(0, |ch| ch.slots.len())
+    }
+
+    /// Data of the slot in every channel
+    pub fn get_slot_data(&self, slot: usize) -> Vec<&[f32]> {
+        self.channels.iter().map(|ch| &ch.slots[slot][..]).collect()
+    }
+
+    /// Writes the selected slot of all channels as a multichannel WAV file at the
+    /// rate the slot was recorded at
+    pub fn export_wav(&self, path: &Path, format: WavFormat, params: &Params) -> io::Result<()> {
+        let slot = self.selected_slot(params);
+        wav::export(
+            path,
+            &self.get_slot_data(slot),
+            self.sample_rates[slot].round() as u32,
+            format,
+        )
+    }
+
+    pub fn get_data_len(&self, channel: usize, slot: usize) -> usize {
         let ch = &self.channels[channel];
-        ch.data.len()
+        ch.slots[slot].len()
     }
 
     pub fn get_voice_info(&self, channel: usize, params: &Params) -> Vec<VoiceInfo> {
-        let data_len_f32 = self.channels[channel].data.len() as f32;
+        let mut info = Vec::with_capacity(MAX_VOICE_STORAGE);
+        self.get_voice_info_into(channel, params, &mut info);
+        info
+    }
 
-        let l = params.loop_length(self.get_data_len(0));
-        self.channels[channel]
-            .voices
-            .iter()
-            .map(|v| {
-                let start = v.loop_start_percent;
-                let end = (v.loop_start_percent + l / data_len_f32) % 1.0;
-                let pos = v.last_sample_index as f32 / data_len_f32;
-                VoiceInfo { start, end, pos }
-            })
-            .collect()
+    /// Replaces the contents of `info`, which does not allocate as long as it has room
+    /// for `MAX_VOICE_STORAGE`
+    pub fn get_voice_info_into(&self, channel: usize, params: &Params, info: &mut Vec<VoiceInfo>) {
+        info.clear();
+        info.extend(self.channels[channel].voices.iter().map(|v| {
+            let data_len = self.get_data_len(channel, v.slot);
+            let data_len_f32 = data_len as f32;
+            let l = v.loop_length(data_len, params);
+            let start = v.loop_start_percent;
+            let end = (v.loop_start_percent + l / data_len_f32) % 1.0;
+            let pos = v.last_sample_index as f32 / data_len_f32;
+            VoiceInfo { start, end, pos }
+        }));
     }
 
     #[cfg(debug_assertions)]
@@ -447,9 +1482,13 @@ impl Sampler {
         let data_lengths: Vec<_> = self
             .channels
             .iter()
-            .map(|ch| ch.data.len())
+            .map(|ch| ch.slots.iter().map(|data| data.len()).collect::<Vec<_>>())
             .collect::<Vec<_>>();
-        self.channels.iter_mut().for_each(|ch| ch.data.clear());
+        self.channels.iter_mut().for_each(|ch| {
+            ch.slots
+                .iter_mut()
+                .for_each(|data| *data = Buffer::default())
+        });
         eprintln!(
             "sampler just before death: {:#?}\ndatas have been clear, had lengths: {:?}",
             self, data_lengths
@@ -0,0 +1,73 @@
+// Turntable-like speed control. The controller sets the speed the platter is heading for and
+// the platter follows with inertia, so steps in controller values turn into smooth changes.
+
+#[derive(Clone, Debug)]
+pub struct Scratch {
+    speed: f32,
+    target: f32,
+}
+
+// close enough to the target to stop moving
+const SETTLED: f32 = 1e-4;
+
+impl Default for Scratch {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            target: 1.0,
+        }
+    }
+}
+
+impl Scratch {
+    /// 0.0 plays at normal speed, 0.5 holds the platter still and 1.0 pulls it backwards at
+    /// normal speed, so a controller at rest leaves playback alone
+    pub fn set_value(&mut self, value: f32) {
+        self.target = 1.0 - 2.0 * value.clamp(0.0, 1.0);
+    }
+
+    /// Moves the speed towards the target over `samples`, `inertia_samples` being the time
+    /// it takes to get about two thirds of the way there
+    pub fn advance(&mut self, samples: usize, inertia_samples: f32) {
+        if inertia_samples <= 0.0 {
+            self.speed = self.target;
+        } else {
+            let weight = (-(samples as f32) / inertia_samples).exp();
+            self.speed = self.target + (self.speed - self.target) * weight;
+        }
+        if (self.speed - self.target).abs() < SETTLED {
+            self.speed = self.target;
+        }
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.speed != self.target
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scratch() {
+        let mut scratch = Scratch::default();
+        assert!(!scratch.is_moving());
+        scratch.set_value(0.5);
+        scratch.advance(100, 100.0);
+        assert!((scratch.speed() - (-1.0f32).exp()).abs() < 1e-6);
+        assert!(scratch.is_moving());
+        scratch.advance(10000, 100.0);
+        assert_eq!(scratch.speed(), 0.0);
+        assert!(!scratch.is_moving());
+
+        // no inertia jumps straight to the target
+        scratch.set_value(1.0);
+        scratch.advance(1, 0.0);
+        assert_eq!(scratch.speed(), -1.0);
+    }
+}
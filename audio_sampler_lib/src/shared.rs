@@ -0,0 +1,157 @@
+// Slot buffers are reference counted so other threads can read them without the audio thread
+// copying anything. The audio thread never writes to a buffer that may be shared: takes and
+// captures go into a spare buffer prepared elsewhere, and buffers the sampler lets go of are
+// retired so the host can drop them off the audio thread.
+
+use std::sync::Arc;
+
+use smallvec::SmallVec;
+
+use crate::onset;
+use crate::snapshot::SlotSnapshot;
+
+pub type Buffer = Arc<Vec<f32>>;
+
+// up to this many channels are handed between threads without allocating
+pub const INLINE_CHANNELS: usize = 8;
+
+/// One buffer per channel
+pub type Buffers = SmallVec<Buffer, INLINE_CHANNELS>;
+
+// onsets past this many are dropped, so slice tables never allocate
+pub const MAX_SLICES: usize = 64;
+
+/// Detected onsets of a slot, empty when slices follow an equal grid
+pub type Slices = SmallVec<usize, MAX_SLICES>;
+
+// keeps the capacity, which a derived clone would drop
+pub(crate) fn copy_buffer(buffer: &Buffer) -> Buffer {
+    let mut copy = Vec::with_capacity(buffer.capacity());
+    copy.extend_from_slice(buffer);
+    Arc::new(copy)
+}
+
+/// A slot's buffers as seen by other threads
+#[derive(Clone, Debug, Default)]
+pub struct SharedSlot {
+    // changes whenever the slot's buffers are replaced
+    pub version: usize,
+    pub sample_rate: f32,
+    pub channels: Buffers,
+    pub slices: Slices,
+}
+
+impl SharedSlot {
+    /// Buffers for `Sampler::replace_slot` with their onsets, data past `max_len` is cut off
+    /// like a take reaching it would be. Allocates, meant for a background thread.
+    pub fn new(
+        mut channels: Vec<Vec<f32>>,
+        sample_rate: f32,
+        max_len: usize,
+        sensitivity: f32,
+    ) -> Self {
+        channels.iter_mut().for_each(|data| data.truncate(max_len));
+        let mut slot = Self {
+            version: 0,
+            sample_rate,
+            channels: channels.into_iter().map(Arc::new).collect(),
+            slices: Slices::new(),
+        };
+        slot.slices = slot.detect_slices(sensitivity);
+        slot
+    }
+
+    /// Buffers for `Sampler::replace_slot` from a snapshot, which should have been through
+    /// `snapshot::prepare` first. Allocates.
+    pub fn from_snapshot(snapshot: SlotSnapshot) -> Self {
+        Self {
+            version: 0,
+            sample_rate: snapshot.sample_rate,
+            channels: snapshot.channels.into_iter().map(Arc::new).collect(),
+            slices: snapshot.slices.into_iter().take(MAX_SLICES).collect(),
+        }
+    }
+
+    /// Runs onset detection on the slot's data. Allocates, meant for a background thread.
+    pub fn detect_slices(&self, sensitivity: f32) -> Slices {
+        let channels: Vec<&[f32]> = self.channels.iter().map(|data| &data[..]).collect();
+        onset::detect_onsets(&channels, sensitivity)
+            .into_iter()
+            .take(MAX_SLICES)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels.first().map_or(0, |data| data.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Buffers a take or capture is written into, prepared off the audio thread. A copy of
+/// `slot` at `version` lets a take keep the slot's contents, otherwise the buffers are
+/// cleared before use.
+#[derive(Debug)]
+pub struct Spare {
+    pub slot: usize,
+    pub version: usize,
+    pub channels: Buffers,
+}
+
+impl Spare {
+    /// `capacity` should be the longest take, which then never has to grow the buffers
+    pub fn new(slot: usize, source: &SharedSlot, channel_count: usize, capacity: usize) -> Self {
+        let channels = (0..channel_count)
+            .map(|i| {
+                let mut data = Vec::with_capacity(capacity);
+                if let Some(source) = source.channels.get(i) {
+                    data.extend_from_slice(&source[..source.len().min(capacity)]);
+                }
+                Arc::new(data)
+            })
+            .collect();
+        Self {
+            slot,
+            version: source.version,
+            channels,
+        }
+    }
+
+    /// Spare that is not a copy of any slot
+    pub fn empty(channel_count: usize, capacity: usize) -> Self {
+        Self::new(usize::MAX, &SharedSlot::default(), channel_count, capacity)
+    }
+}
+
+impl Clone for Spare {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot,
+            version: self.version,
+            channels: self.channels.iter().map(copy_buffer).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spare() {
+        let source = SharedSlot {
+            version: 3,
+            sample_rate: 44100.0,
+            channels: [Arc::new(vec![1.0, 2.0, 3.0])].into_iter().collect(),
+            slices: Slices::new(),
+        };
+        // missing channels are left empty, data past the capacity is cut off
+        let spare = Spare::new(1, &source, 2, 2);
+        assert_eq!(spare.version, 3);
+        assert_eq!(*spare.channels[0], vec![1.0, 2.0]);
+        assert!(spare.channels[1].is_empty());
+        assert!(spare.channels.iter().all(|data| data.capacity() == 2));
+    }
+}
@@ -0,0 +1,225 @@
+use std::io;
+
+use crate::audio_file;
+use crate::audio_file::{invalid_data, is_valid_sample_rate};
+use crate::shared::SharedSlot;
+
+const VERSION: u32 = 1;
+
+// Recorded slot with what is needed to restore it at another sample rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotSnapshot {
+    pub slot: usize,
+    pub sample_rate: f32,
+    pub channels: Vec<Vec<f32>>,
+    pub slices: Vec<usize>,
+}
+
+impl SlotSnapshot {
+    pub fn encoded_len(&self) -> usize {
+        let samples: usize = self.channels.iter().map(|data| data.len()).sum();
+        5 * 4 + self.slices.len() * 4 + samples * 4
+    }
+}
+
+/// Copies of the non-empty slots, `slots` being indexed by slot. Slots that would take the
+/// total encoded size over `max_bytes` are left out.
+pub fn snapshot_slots(slots: &[SharedSlot], max_bytes: usize) -> Vec<SlotSnapshot> {
+    let mut total = 0;
+    let mut snapshots = vec![];
+    for (slot, shared) in slots.iter().enumerate() {
+        if shared.channels.iter().all(|data| data.is_empty()) {
+            continue;
+        }
+        let snapshot = SlotSnapshot {
+            slot,
+            sample_rate: shared.sample_rate,
+            channels: shared.channels.iter().map(|data| data.to_vec()).collect(),
+            slices: shared.slices.to_vec(),
+        };
+        if total + snapshot.encoded_len() <= max_bytes {
+            total += snapshot.encoded_len();
+            snapshots.push(snapshot);
+        }
+    }
+    snapshots
+}
+
+/// Converts a snapshot to the channel count and sample rate it is restored at
+pub fn prepare(snapshot: SlotSnapshot, channel_count: usize, sample_rate: f32) -> SlotSnapshot {
+    let channels: Vec<Vec<f32>> = audio_file::map_channels(snapshot.channels, channel_count)
+        .iter()
+        .map(|data| audio_file::resample(data, snapshot.sample_rate, sample_rate))
+        .collect();
+    // resample leaves data with an invalid rate as it is, the slices stay with it
+    let ratio = if is_valid_sample_rate(snapshot.sample_rate) && is_valid_sample_rate(sample_rate) {
+        sample_rate as f64 / snapshot.sample_rate as f64
+    } else {
+        1.0
+    };
+    let len = channels.first().map_or(0, |data| data.len());
+    let slices = snapshot
+        .slices
+        .iter()
+        .map(|&slice| (slice as f64 * ratio).round() as usize)
+        .filter(|&slice| slice < len)
+        .collect();
+    SlotSnapshot {
+        slot: snapshot.slot,
+        sample_rate,
+        channels,
+        slices,
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, x: u32) {
+    out.extend_from_slice(&x.to_le_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u32(&mut self) -> io::Result<u32> {
+        let b = self
+            .bytes
+            .get(self.at..self.at + 4)
+            .ok_or_else(|| invalid_data("truncated snapshot"))?;
+        self.at += 4;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        self.u32().map(f32::from_bits)
+    }
+}
+
+/// Binary layout: version, slot count, then per slot its index, sample rate, channel
+/// count, frame count, slice count, slices and planar samples. All little endian.
+pub fn encode(snapshots: &[SlotSnapshot]) -> Vec<u8> {
+    let len: usize = snapshots.iter().map(|s| s.encoded_len()).sum();
+    let mut out = Vec::with_capacity(8 + len);
+    put_u32(&mut out, VERSION);
+    put_u32(&mut out, snapshots.len() as u32);
+    for s in snapshots {
+        let frames = s.channels.iter().map(|data| data.len()).max().unwrap_or(0);
+        put_u32(&mut out, s.slot as u32);
+        put_u32(&mut out, s.sample_rate.to_bits());
+        put_u32(&mut out, s.channels.len() as u32);
+        put_u32(&mut out, frames as u32);
+        put_u32(&mut out, s.slices.len() as u32);
+        for &slice in &s.slices {
+            put_u32(&mut out, slice as u32);
+        }
+        for data in &s.channels {
+            for i in 0..frames {
+                put_u32(&mut out, data.get(i).cloned().unwrap_or(0.0).to_bits());
+            }
+        }
+    }
+    out
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<Vec<SlotSnapshot>> {
+    let mut r = Reader { bytes, at: 0 };
+    if r.u32()? != VERSION {
+        return Err(invalid_data("unsupported snapshot version"));
+    }
+    let count = r.u32()?;
+    let mut snapshots = vec![];
+    for _ in 0..count {
+        let slot = r.u32()? as usize;
+        let sample_rate = r.f32()?;
+        if !is_valid_sample_rate(sample_rate) {
+            return Err(invalid_data("invalid snapshot sample rate"));
+        }
+        let channel_count = r.u32()? as usize;
+        let frames = r.u32()? as usize;
+        let slice_count = r.u32()? as usize;
+        // sizes come from the data, check them before allocating
+        let len = channel_count
+            .saturating_mul(frames)
+            .saturating_add(slice_count)
+            .saturating_mul(4);
+        if len > bytes.len() - r.at {
+            return Err(invalid_data("truncated snapshot"));
+        }
+        let slices = (0..slice_count)
+            .map(|_| r.u32().map(|x| x as usize))
+            .collect::<io::Result<_>>()?;
+        let channels = (0..channel_count)
+            .map(|_| (0..frames).map(|_| r.f32()).collect::<io::Result<_>>())
+            .collect::<io::Result<_>>()?;
+        snapshots.push(SlotSnapshot {
+            slot,
+            sample_rate,
+            channels,
+            slices,
+        });
+    }
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let snapshots = vec![
+            SlotSnapshot {
+                slot: 3,
+                sample_rate: 48000.0,
+                channels: vec![vec![1.0, 2.0, 3.0], vec![-1.0, -2.0, -3.0]],
+                slices: vec![0, 2],
+            },
+            SlotSnapshot {
+                slot: 0,
+                sample_rate: 44100.0,
+                channels: vec![vec![0.5]],
+                slices: vec![],
+            },
+        ];
+        let bytes = encode(&snapshots);
+        assert_eq!(
+            bytes.len(),
+            8 + snapshots.iter().map(|s| s.encoded_len()).sum::<usize>()
+        );
+        assert_eq!(decode(&bytes).unwrap(), snapshots);
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut invalid = snapshots.clone();
+        invalid[1].sample_rate = 0.0;
+        assert!(decode(&encode(&invalid)).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid_rate() {
+        for sample_rate in [1e-30, 1e9, f32::NAN, -44100.0] {
+            let bytes = encode(&[SlotSnapshot {
+                slot: 0,
+                sample_rate,
+                channels: vec![vec![0.5; 4]],
+                slices: vec![],
+            }]);
+            let err = decode(&bytes).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_prepare() {
+        let snapshot = SlotSnapshot {
+            slot: 1,
+            sample_rate: 22050.0,
+            channels: vec![vec![0.0; 100]],
+            slices: vec![0, 30, 99],
+        };
+        let prepared = prepare(snapshot, 2, 44100.0);
+        assert_eq!(prepared.channels.len(), 2);
+        assert_eq!(prepared.channels[1].len(), 200);
+        assert_eq!(prepared.slices, vec![0, 60, 198]);
+    }
+}
@@ -3,8 +3,42 @@ mod test {
     use crate::common_types::*;
     use crate::sampler::*;
     use crate::time_value::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
     use std::time::Instant;
 
+    // counts allocations made by the current thread, tests run in parallel
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn count_allocations<F: FnOnce()>(f: F) -> usize {
+        let before = ALLOCATIONS.with(|n| n.get());
+        f();
+        ALLOCATIONS.with(|n| n.get()) - before
+    }
+
     #[derive(Clone, Debug)]
     struct EasyHost {
         pub sampler: Sampler,
@@ -19,7 +53,7 @@ mod test {
                 params: Params {
                     loop_mode: LoopMode::Loop,
                     attack_samples: 0,
-                    decay_samples: 0,
+                    release_samples: 0,
                     loop_length: TimeOrRatio::Ratio(1.0),
                     ..Params::default()
                 },
@@ -57,17 +91,22 @@ mod test {
         }
         pub fn start_playing(&mut self, start_position: f32) {
             self.sampler
-                .start_playing(start_position, Note::new(0, 0), 1.0, &self.params);
+                .start_playing(start_position, Note::new(0, 0), 1.0, false, &self.params);
         }
         pub fn start_playing_note(&mut self, start_position: f32, note: u8) {
-            self.sampler
-                .start_playing(start_position, Note::new(note, 0), 1.0, &self.params);
+            self.sampler.start_playing(
+                start_position,
+                Note::new(note, 0),
+                1.0,
+                false,
+                &self.params,
+            );
         }
         pub fn stop_playing_note(&mut self, note: u8) {
             self.sampler.stop_playing(Note::new(note, 0), &self.params);
         }
         pub fn start_recording(&mut self) {
-            self.sampler.start_recording();
+            self.sampler.start_recording(&self.params);
         }
         pub fn stop_recording(&mut self) {
             self.sampler.stop_recording(&self.params);
@@ -100,4 +139,72 @@ mod test {
         }
         eprintln!("{:?}", t.elapsed());
     }
+
+    #[test]
+    fn test_process_does_not_allocate() {
+        let mut h = EasyHost::default();
+        h.record((0..1000).map(|i| (i as f32 * 0.1).sin()));
+        h.params.max_voices = 0;
+        h.params.release_samples = 10;
+        let mut info = Vec::with_capacity(MAX_VOICE_STORAGE);
+        let mut process = |h: &mut EasyHost, n: usize| {
+            count_allocations(|| {
+                for _ in 0..n {
+                    let mut x = 0.5;
+                    h.sampler.process_frame(&mut [&mut x], &h.params);
+                    h.sampler.get_voice_info_into(0, &h.params, &mut info);
+                }
+            })
+        };
+
+        // more voices than the limit fade out the oldest ones, more than there is room
+        // for replace them
+        for note in 0..MAX_VOICE_STORAGE as u8 + 8 {
+            h.start_playing_note(note as f32 / 1000.0, note);
+        }
+        assert_eq!(h.sampler.channels[0].voices.len(), MAX_VOICE_STORAGE);
+        assert_eq!(process(&mut h, 100), 0);
+
+        let modes = [
+            (LoopMode::Loop, PlaybackMode::Resample, 0),
+            (LoopMode::PingPong, PlaybackMode::Resample, 50),
+            (LoopMode::PlayOnce, PlaybackMode::Resample, 0),
+            (LoopMode::Loop, PlaybackMode::Granular, 0),
+        ];
+        for (loop_mode, playback_mode, crossfade) in modes {
+            h.params.loop_mode = loop_mode;
+            h.params.playback_mode = playback_mode;
+            h.params.loop_crossfade_samples = crossfade;
+            for note in 0..8 {
+                h.stop_playing_note(note);
+            }
+            assert_eq!(process(&mut h, 500), 0);
+            for note in 0..8 {
+                h.start_playing_note(0.0, note);
+            }
+            // voices finishing and being removed
+            assert_eq!(process(&mut h, 1500), 0);
+        }
+        assert_eq!(h.sampler.channels[0].voices.capacity(), MAX_VOICE_STORAGE);
+
+        let input = [0.5; 64];
+        let mut output = [0.0; 64];
+        let events = [
+            (
+                10,
+                Command::SetNoteSpeed {
+                    note: Note::new(1, 0),
+                    speed: 2.0,
+                },
+            ),
+            (20, Command::SetSpeed { speed: 0.5 }),
+        ];
+        let allocations = count_allocations(|| {
+            for _ in 0..10 {
+                h.sampler
+                    .process_block(&[&input[..]], &mut [&mut output[..]], &events, &h.params);
+            }
+        });
+        assert_eq!(allocations, 0);
+    }
 }
@@ -1,6 +1,10 @@
 #[cfg(test)]
 mod test {
-    use crate::common_types::{InitParams, Note, NoteOffBehaviour, Params, Transport};
+    use crate::common_types::{
+        Command, InitParams, Interpolation, Note, NoteOffBehaviour, OutputRouting, Params,
+        PitchTracking, PlaybackMode, RecordingMode, Retrigger, Transport, VoiceStealing, WavFormat,
+        MAX_VOICES,
+    };
     use crate::sampler::{LoopMode, Sampler};
     use crate::time_value::{TimeOrRatio, TimeValue};
 
@@ -28,7 +32,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::PlayOnce,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             note_off_behavior: NoteOffBehaviour::Decay,
             ..Params::default()
@@ -80,14 +84,20 @@ mod test {
                     let (todo, rem) = self.cmds.iter().partition(|(at, _)| *at == self.now);
                     for &(_, cmd) in &todo {
                         match cmd {
-                            Cmd::StartPlaying { start_percent: pos } => {
-                                self.sampler
-                                    .start_playing(pos, Note::new(11, 0), 1.0, &self.params)
-                            }
+                            Cmd::StartPlaying { start_percent: pos } => self.sampler.start_playing(
+                                pos,
+                                Note::new(11, 0),
+                                1.0,
+                                false,
+                                &self.params,
+                            ),
                             Cmd::StopPlaying => {
                                 self.sampler.stop_playing(Note::new(11, 0), &self.params)
                             }
-                            Cmd::StartRecording => self.sampler.start_recording(),
+                            Cmd::StartRecording => {
+                                self.sampler.update_buffers(&self.params);
+                                assert!(self.sampler.start_recording(&self.params));
+                            }
                             Cmd::StopRecording => self.sampler.stop_recording(&self.params),
                         }
                     }
@@ -107,7 +117,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::PlayOnce,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(0.5),
             ..base_params()
         };
@@ -193,7 +203,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::PlayOnce,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             ..base_params()
         };
@@ -230,7 +240,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::Loop,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             ..base_params()
         };
@@ -324,7 +334,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::Loop,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(0.6),
             speed: -1.0,
             ..base_params()
@@ -362,7 +372,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::Loop,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             ..base_params()
         };
@@ -403,7 +413,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::Loop,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             speed: -1.0,
             ..base_params()
@@ -444,7 +454,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::Loop,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             speed: -1.0,
             ..base_params()
@@ -482,7 +492,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::PingPong,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             ..base_params()
         };
@@ -505,7 +515,7 @@ mod test {
         let params = Params {
             loop_mode: LoopMode::PingPong,
             attack_samples: 0,
-            decay_samples: 0,
+            release_samples: 0,
             loop_length: TimeOrRatio::Ratio(1.0),
             ..base_params()
         };
@@ -527,7 +537,7 @@ mod test {
         host.schedule(input.len(), Cmd::StopRecording);
         host.run_input(input);
 
-        let wave = host.sampler().get_waveform_summary(20);
+        let wave = host.sampler().get_waveform_summary(0, 20);
         for (i, x) in wave.data.iter().take(20).enumerate() {
             eprintln!("{:<4}: {:?}", i, x);
         }
@@ -554,7 +564,7 @@ mod test {
                 params: Params {
                     loop_mode: LoopMode::Loop,
                     attack_samples: 0,
-                    decay_samples: 0,
+                    release_samples: 0,
                     loop_length: TimeOrRatio::Ratio(1.0),
                     fixed_size_samples: (TimeValue::bars(1.0).as_samples(&transport) as usize),
                     ..params
@@ -591,13 +601,24 @@ mod test {
         }
         pub fn start_playing(&mut self, start_position: f32) {
             self.sampler
-                .start_playing(start_position, Note::new(0, 0), 1.0, &self.params);
+                .start_playing(start_position, Note::new(0, 0), 1.0, false, &self.params);
+        }
+        pub fn start_playing_note(&mut self, start_position: f32, note: u8) {
+            self.sampler.start_playing(
+                start_position,
+                Note::new(note, 0),
+                1.0,
+                false,
+                &self.params,
+            );
         }
         pub fn start_recording(&mut self) {
-            self.sampler.start_recording();
+            self.sampler.update_buffers(&self.params);
+            self.sampler.start_recording(&self.params);
         }
         pub fn stop_recording(&mut self) {
             self.sampler.stop_recording(&self.params);
+            self.sampler.update_buffers(&self.params);
         }
     }
 
@@ -653,6 +674,758 @@ mod test {
         assert_eq!(h.run(3), vec![333.0, 111.0, 222.0]);
     }
 
+    #[test]
+    fn test_fixed_length_starts_on_bar() {
+        // one quarter note per sample, four samples per bar
+        let transport = Transport {
+            sample_rate: 4.0,
+            tempo: 240.0,
+            pos_samples: 1.0,
+            ..Transport::default()
+        };
+        let mut h = EasyHost::from_transport(transport.clone());
+        h.params.transport = transport;
+        h.params.recording_mode = RecordingMode::FixedLength;
+
+        // released before the bar line, the take still starts on it and runs for one bar
+        h.start_recording();
+        h.run_input(vec![1.0]);
+        h.stop_recording();
+        h.run_input(vec![2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(h.sampler.get_data_len(0, 0), 4);
+        h.start_playing(0.0);
+        assert_eq!(h.run(4), vec![4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn test_interpolated_half_speed() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+        h.params.speed = 0.5;
+        h.params.interpolation = Interpolation::Linear;
+        h.start_playing(0.0);
+        assert_eq!(h.run(5), vec![1.0, 1.5, 2.0, 2.5, 3.0]);
+
+        h.params.interpolation = Interpolation::None;
+        assert_eq!(h.run(4), vec![3.0, 4.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_sample_slots() {
+        let mut h = EasyHost::default();
+        h.record(one_to_five());
+        h.params.sample_id = 1;
+        h.record(five_tens());
+        assert_eq!(h.sampler.get_data_len(0, 0), 5);
+        assert_eq!(h.sampler.get_data_len(0, 1), 5);
+
+        // voice keeps playing the slot it was started from
+        h.start_playing(0.0);
+        h.params.sample_id = 0;
+        assert_eq!(h.run(3), vec![100.0, 100.0, 100.0]);
+        h.sampler.stop_playing(Note::new(0, 0), &h.params);
+        h.start_playing(0.0);
+        assert_eq!(h.run(3), vec![1.0, 2.0, 3.0]);
+
+        // empty slot does not start a voice
+        h.sampler.stop_playing(Note::new(0, 0), &h.params);
+        h.run(1);
+        h.params.sample_id = 2;
+        h.start_playing(0.0);
+        assert!(h.sampler.channels[0].voices.is_empty());
+    }
+
+    #[test]
+    fn test_commit_capture() {
+        let mut h = EasyHost {
+            sampler: Sampler::new(
+                1,
+                &InitParams {
+                    capture_buffer_samples: 8,
+                    ..InitParams::default()
+                },
+            ),
+            ..EasyHost::default()
+        };
+        h.params.capture_length = TimeValue::samples(5.0);
+        h.run_input(one_to_ten());
+        h.sampler.commit_capture(&h.params);
+        assert_eq!(h.sampler.get_data_len(0, 0), 5);
+        h.start_playing(0.0);
+        assert_eq!(h.run(6), vec![6.0, 7.0, 8.0, 9.0, 10.0, 6.0]);
+
+        // longer than the capture buffer, commits everything available
+        h.params.capture_length = TimeValue::samples(20.0);
+        h.sampler.commit_capture(&h.params);
+        assert_eq!(h.sampler.get_data_len(0, 0), 8);
+    }
+
+    #[test]
+    fn test_commit_capture_on_bar() {
+        // one quarter note per sample, four samples per bar
+        let transport = Transport {
+            sample_rate: 4.0,
+            tempo: 240.0,
+            pos_samples: 0.0,
+            ..Transport::default()
+        };
+        let mut h = EasyHost {
+            sampler: Sampler::new(
+                1,
+                &InitParams {
+                    capture_buffer_samples: 8,
+                    ..InitParams::default()
+                },
+            ),
+            ..EasyHost::from_transport(transport.clone())
+        };
+        h.params.transport = transport;
+        h.params.capture_length = TimeValue::bars(1.0);
+
+        // two samples into the third bar, the capture is the whole second bar
+        h.run_input(one_to_ten());
+        h.sampler.commit_capture(&h.params);
+        assert_eq!(h.sampler.get_data_len(0, 0), 4);
+        h.start_playing(0.0);
+        assert_eq!(h.run(4), vec![5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(h.sampler.capture_truncation_count(0), 0);
+
+        // two bars reach back past the start of the capture buffer
+        h.params.capture_length = TimeValue::bars(2.0);
+        h.sampler.commit_capture(&h.params);
+        assert_eq!(h.sampler.capture_truncation_count(0), 1);
+    }
+
+    #[test]
+    fn test_max_recording_length() {
+        let mut h = EasyHost {
+            sampler: Sampler::new(
+                1,
+                &InitParams {
+                    max_recording_samples: 4,
+                    ..InitParams::default()
+                },
+            ),
+            ..EasyHost::default()
+        };
+        h.record(one_to_ten());
+        assert_eq!(h.sampler.get_data_len(0, 0), 4);
+        assert_eq!(h.sampler.recording_overflow_count(0), 1);
+        assert_eq!(h.sampler.channels[0].slots[0].capacity(), 4);
+
+        // loaded data is cut off the same way
+        h.sampler.load_slot(1, vec![vec![1.0; 6]], &h.params);
+        assert_eq!(h.sampler.get_data_len(0, 1), 4);
+        h.params.sample_id = 1;
+        h.start_playing(0.0);
+        assert_eq!(h.run(4), vec![1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_shared_slots() {
+        let mut h = EasyHost::default();
+        h.record(one_to_five());
+        let shared = h.sampler.share_slot(0).unwrap();
+        assert_eq!(*shared.channels[0], one_to_five());
+
+        // the shared buffers are left alone, the take goes into the spare copy of them
+        h.params.recording_mode = RecordingMode::Overdub;
+        h.start_recording();
+        h.run_input(five_tens());
+        h.sampler.stop_recording(&h.params);
+        assert_eq!(*shared.channels[0], one_to_five());
+        assert_eq!(
+            h.sampler.get_slot_data(0)[0],
+            &[101.0, 102.0, 103.0, 104.0, 105.0]
+        );
+        assert!(h.sampler.get_slot_version(0) > shared.version);
+        assert_eq!(h.sampler.drain_retired().count(), 1);
+
+        // no spare to fall back on
+        let _shared = h.sampler.share_slot(0).unwrap();
+        h.sampler.start_recording(&h.params);
+        assert!(!h.sampler.is_recording(0));
+        assert_eq!(h.sampler.dropped_take_count(), 1);
+    }
+
+    #[test]
+    fn test_overdub_stale_spare() {
+        let mut h = EasyHost::default();
+        h.record(one_to_five());
+        let stale = crate::shared::Spare::new(0, &h.sampler.share_slot(0).unwrap(), 1, 100);
+        h.record(five_tens());
+        let _shared = h.sampler.share_slot(0).unwrap();
+
+        // the overdub would start from an empty buffer, the loop is kept instead
+        h.sampler.set_spare(stale);
+        h.params.recording_mode = RecordingMode::Overdub;
+        assert!(!h.sampler.start_recording(&h.params));
+        h.run_input(one_to_five());
+        assert_eq!(h.sampler.get_slot_data(0)[0], &five_tens()[..]);
+        assert_eq!(h.sampler.dropped_take_count(), 1);
+
+        // a take that replaces the contents can still use it
+        assert!(h.sampler.has_spare());
+        h.params.recording_mode = RecordingMode::Free;
+        assert!(h.sampler.start_recording(&h.params));
+        h.run_input(one_to_five());
+        h.sampler.stop_recording(&h.params);
+        assert_eq!(h.sampler.get_slot_data(0)[0], &one_to_five()[..]);
+    }
+
+    #[test]
+    fn test_voice_stealing() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+        h.params.max_voices = 2;
+
+        let notes = |h: &EasyHost| {
+            h.sampler.channels[0]
+                .voices
+                .iter()
+                .filter(|v| !v.stolen)
+                .map(|v| v.note.note)
+                .collect::<Vec<_>>()
+        };
+
+        h.params.voice_stealing = VoiceStealing::Oldest;
+        h.start_playing_note(0.0, 20);
+        h.start_playing_note(0.0, 10);
+        h.start_playing_note(0.0, 30);
+        assert_eq!(notes(&h), vec![10, 30]);
+
+        h.params.voice_stealing = VoiceStealing::LowestPriority;
+        h.start_playing_note(0.0, 40);
+        assert_eq!(notes(&h), vec![30, 40]);
+
+        h.params.voice_stealing = VoiceStealing::SameNote;
+        h.start_playing_note(0.0, 40);
+        assert_eq!(notes(&h), vec![30, 40]);
+        assert_eq!(h.sampler.iter_active_notes(0).count(), 2);
+
+        // stolen voices fade out quickly and get removed
+        h.run(1000);
+        assert_eq!(h.sampler.channels[0].voices.len(), 2);
+    }
+
+    #[test]
+    fn test_voice_stealing_past_max_voices() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+        // no limit still stops at MAX_VOICES, fading out voices past it
+        h.params.max_voices = 0;
+        for note in 0..MAX_VOICES as u8 + 8 {
+            h.start_playing_note(0.0, note);
+        }
+        let voices = &h.sampler.channels[0].voices;
+        assert_eq!(voices.len(), MAX_VOICES + 8);
+        assert_eq!(voices.iter().filter(|v| v.stolen).count(), 8);
+        assert!(voices.iter().take(8).all(|v| v.stolen));
+    }
+
+    #[test]
+    fn test_retrigger() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+
+        h.params.retrigger = Retrigger::Restart;
+        h.start_playing_note(0.0, 12);
+        assert_eq!(h.run(3), vec![1.0, 2.0, 3.0]);
+        h.start_playing_note(0.5, 12);
+        assert_eq!(h.run(3), vec![6.0, 7.0, 8.0]);
+        assert_eq!(h.sampler.channels[0].voices.len(), 1);
+
+        h.params.retrigger = Retrigger::Ignore;
+        h.start_playing_note(0.0, 12);
+        assert_eq!(h.run(2), vec![9.0, 10.0]);
+        assert_eq!(h.sampler.channels[0].voices.len(), 1);
+
+        h.params.retrigger = Retrigger::Layer;
+        h.start_playing_note(0.0, 12);
+        assert_eq!(h.run(2), vec![1.0 + 1.0, 2.0 + 2.0]);
+        assert_eq!(h.sampler.channels[0].voices.len(), 2);
+        assert_eq!(h.sampler.iter_active_notes(0).count(), 1);
+
+        // each note off releases one of the layered voices
+        h.sampler.stop_playing(Note::new(12, 0), &h.params);
+        assert_eq!(h.sampler.iter_active_notes(0).count(), 1);
+        h.sampler.stop_playing(Note::new(12, 0), &h.params);
+        assert_eq!(h.sampler.iter_active_notes(0).count(), 0);
+    }
+
+    #[test]
+    fn test_granular_time_stretch() {
+        let mut h = EasyHost::default();
+        h.record(ten_tens());
+        h.params.playback_mode = PlaybackMode::Granular;
+        h.params.grain_samples = 4;
+        h.params.speed = 0.5;
+        h.params.pitch = 2.0;
+        h.start_playing(0.0);
+        let output = h.run(12);
+        // overlapping grains keep level constant once the first grain has faded in
+        for x in &output[2..] {
+            assert!((x - 100.0).abs() < 1e-3, "{:?}", output);
+        }
+        // loop position advances at speed regardless of pitch
+        assert_eq!(h.sampler.channels[0].voices[0].played, 6.0);
+    }
+
+    #[test]
+    fn test_loop_crossfade() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+        h.params.loop_length = TimeOrRatio::Ratio(0.5);
+        h.params.loop_crossfade_samples = 2;
+        // loop over 6..=10, end of the loop fades into 4, 5 which precede the loop start
+        h.start_playing(0.5);
+        assert_eq!(h.run(7), vec![6.0, 7.0, 8.0, 6.5, 5.0, 6.0, 7.0]);
+
+        h.sampler.stop_playing(Note::new(0, 0), &h.params);
+        h.run(1);
+        h.params.loop_mode = LoopMode::PingPong;
+        h.start_playing(0.5);
+        // turning points are blended with material mirrored past the boundary: 5, 4 before
+        // the start and 1, 2 after the end (wrapped around)
+        assert_eq!(
+            h.run(10),
+            vec![5.5, 6.25, 8.0, 7.25, 5.5, 5.5, 7.25, 8.0, 6.25, 5.5]
+        );
+    }
+
+    #[test]
+    fn test_onset_slices() {
+        let mut h = EasyHost::default();
+        let mut data = vec![0.001; 20000];
+        for &start in &[0, 5000, 12345] {
+            for i in 0..2000 {
+                data[start + i] = if i % 2 == 0 { 1.0 } else { -1.0 };
+            }
+        }
+        h.params.onset_sensitivity = 0.5;
+        h.sampler.start_recording(&h.params);
+        h.run_input(data.clone());
+        h.sampler.stop_recording(&h.params);
+        let slice_start = |h: &mut EasyHost, slice: usize| {
+            h.sampler
+                .start_playing_slice(slice, Note::new(12, 0), 1.0, false, &h.params);
+            let start = h.sampler.channels[0].voices[0].loop_start_percent;
+            h.sampler.stop_playing(Note::new(12, 0), &h.params);
+            h.run(1);
+            start
+        };
+        // the grid until onsets have been detected off the audio thread
+        assert_eq!(h.sampler.stale_slices(), Some(0));
+        assert_eq!(slice_start(&mut h, 1), 1.0 / 16.0);
+        h.sampler.update_buffers(&h.params);
+        assert_eq!(h.sampler.stale_slices(), None);
+        assert_eq!(slice_start(&mut h, 1), 5000.0 / 20000.0);
+        assert_eq!(slice_start(&mut h, 2), 12345.0 / 20000.0);
+        // slices past the last onset follow the grid
+        assert_eq!(slice_start(&mut h, 3), 3.0 / 16.0);
+
+        // equal grid when detection is disabled
+        h.params.onset_sensitivity = 0.0;
+        h.record(data);
+        assert_eq!(slice_start(&mut h, 3), 3.0 / 16.0);
+        h.params.slice_length = TimeOrRatio::Ratio(0.25);
+        assert_eq!(slice_start(&mut h, 5), 0.25);
+
+        // a shorter last slice when the length doesn't divide the buffer
+        h.params.slice_length = TimeOrRatio::Time(TimeValue::Samples(8000.0));
+        assert_eq!(h.params.slice_count(20000), 3);
+        assert_eq!(slice_start(&mut h, 2), 0.8);
+        assert_eq!(slice_start(&mut h, 3), 0.0);
+
+        // an 1/8 note at 120 bpm
+        h.params.slice_length = TimeOrRatio::Time(TimeValue::QuarterNotes(0.5));
+        assert_eq!(h.params.slice_ratio(20000), 11025.0 / 20000.0);
+        assert_eq!(h.params.slice_count(20000), 2);
+    }
+
+    #[test]
+    fn test_export_wav() {
+        let mut h = EasyHost::default();
+        h.record(one_to_five());
+        let path = std::env::temp_dir().join("audio_sampler_lib_test_export.wav");
+        h.sampler
+            .export_wav(&path, WavFormat::Float32, &h.params)
+            .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let samples: Vec<f32> = bytes[bytes.len() - 5 * 4..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(samples, one_to_five());
+    }
+
+    #[test]
+    fn test_load_file() {
+        let mut h = EasyHost::default();
+        let path = std::env::temp_dir().join("audio_sampler_lib_test_load.wav");
+        let data = one_to_five();
+        crate::wav::export(&path, &[&data, &data], 44100, WavFormat::Float32).unwrap();
+        h.params.sample_id = 1;
+        h.sampler.load_file(&path, &h.params).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // stereo file is mixed down to the single channel
+        assert_eq!(h.sampler.get_data_len(0, 1), 5);
+        h.start_playing(0.0);
+        assert_eq!(h.run(5), one_to_five());
+    }
+
+    #[test]
+    fn test_snapshot_slots() {
+        let mut h = EasyHost::default();
+        h.params.onset_sensitivity = 0.5;
+        h.record(one_to_ten());
+        h.params.sample_id = 2;
+        h.record(one_to_five());
+        assert!(h
+            .sampler
+            .set_slices(2, h.sampler.get_slot_version(2), &[0, 3]));
+        let version = h.sampler.get_data_version();
+
+        // slot 0 takes 60 bytes, slot 2 does not fit in what is left
+        let snapshots = h.sampler.snapshot_slots(100);
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].slot, 0);
+        let snapshots = h.sampler.snapshot_slots(usize::MAX);
+        assert_eq!(snapshots.len(), 2);
+
+        let bytes = crate::snapshot::encode(&snapshots);
+        let mut restored = EasyHost::default();
+        restored
+            .sampler
+            .restore_slots(crate::snapshot::decode(&bytes).unwrap());
+        assert_eq!(restored.sampler.get_slot_data(0), vec![&one_to_ten()[..]]);
+        assert_eq!(restored.sampler.get_slot_data(2), vec![&one_to_five()[..]]);
+        assert_eq!(restored.sampler.get_slices(2), &[0, 3]);
+        assert_eq!(h.sampler.get_data_version(), version);
+    }
+
+    #[test]
+    fn test_reversed_voice() {
+        let run = |reverse_speed: f32, reverse: bool| {
+            let mut h = EasyHost::default();
+            h.record(one_to_ten());
+            h.params.reverse_speed = reverse_speed;
+            h.sampler
+                .start_playing(0.5, Note::new(0, 0), 1.0, reverse, &h.params);
+            h.run(10)
+        };
+        let forward = run(1.0, false);
+        let backward = run(-1.0, false);
+        assert_ne!(forward, backward);
+        assert_eq!(run(1.0, true), backward);
+        // global reverse flips reversed voices back
+        assert_eq!(run(-1.0, true), forward);
+    }
+
+    #[test]
+    fn test_play_whole() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+        h.params.loop_length = TimeOrRatio::Ratio(0.5);
+        h.start_playing(0.0);
+        assert_eq!(h.run(10), [one_to_five(), one_to_five()].concat());
+        h.sampler.stop_playing(Note::new(0, 0), &h.params);
+        h.run(10);
+
+        // loop length is ignored, also by params changing while the voice plays
+        h.sampler
+            .start_playing_whole(Note::new(1, 0), 1.0, false, &h.params);
+        assert_eq!(h.run(10), one_to_ten());
+        h.params.loop_length = TimeOrRatio::Ratio(0.2);
+        assert_eq!(h.run(10), one_to_ten());
+    }
+
+    #[test]
+    fn test_pitch_tracking() {
+        let linear = |st: f32| PitchTracking::Linear.speed(st, 1.0);
+        let exponential = |st: f32| PitchTracking::Exponential.speed(st, 1.0);
+        assert_eq!(linear(12.0), 2.0);
+        assert_eq!(linear(-12.0), 0.0);
+        assert_eq!(linear(-24.0), -1.0);
+        assert_eq!(exponential(0.0), 1.0);
+        assert_eq!(exponential(12.0), 2.0);
+        assert_eq!(exponential(-12.0), 0.5);
+        assert_eq!(exponential(-24.0), 0.25);
+        assert!((exponential(7.0) - 1.4983071).abs() < 1e-6);
+        assert_eq!(PitchTracking::Exponential.speed(6.0, 2.0), 2.0);
+    }
+
+    #[test]
+    fn test_convert_slots() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+        h.sampler
+            .set_slices(0, h.sampler.get_slot_version(0), &[0, 4]);
+        h.params.transport.sample_rate = 22050.0;
+        h.params.sample_id = 1;
+        h.record(one_to_five());
+        assert_eq!(h.sampler.get_slot_sample_rate(0), 44100.0);
+        assert_eq!(h.sampler.get_slot_sample_rate(1), 22050.0);
+        assert_eq!(h.sampler.snapshot_slots(usize::MAX)[1].sample_rate, 22050.0);
+
+        let version = h.sampler.get_data_version();
+        h.sampler.convert_slots(88200.0);
+        assert_eq!(h.sampler.get_data_len(0, 0), 20);
+        assert_eq!(h.sampler.get_data_len(0, 1), 20);
+        assert_eq!(h.sampler.get_slices(0), &[0, 8]);
+        assert_eq!(h.sampler.get_slot_sample_rate(1), 88200.0);
+        assert_ne!(h.sampler.get_data_version(), version);
+
+        // nothing to do at the same rate
+        let version = h.sampler.get_data_version();
+        h.sampler.convert_slots(88200.0);
+        assert_eq!(h.sampler.get_data_version(), version);
+    }
+
+    #[test]
+    fn test_process_block_with_record_inputs() {
+        let mut h = EasyHost::default();
+        let main: Vec<f32> = (0..10).map(|x| -(x as f32)).collect();
+        let record = one_to_ten();
+        let mut output = vec![0.0; 10];
+        let events = [(0, Command::StartRecording), (10, Command::StopRecording)];
+        h.sampler.process_block_with_record_inputs(
+            &[&main],
+            &[&record],
+            &mut [&mut output],
+            &events,
+            &h.params,
+        );
+        // main input is passed through while the sidechain is recorded
+        assert_eq!(output, main);
+        assert_eq!(h.sampler.get_slot_data(0), vec![&record[..]]);
+    }
+
+    #[test]
+    fn test_round_robin_skips_ignored_retriggers() {
+        let mut h = EasyHost::default();
+        h.record(one_to_ten());
+        h.params.output_routing = OutputRouting::RoundRobin;
+        h.params.output_count = 2;
+        h.params.retrigger = Retrigger::Ignore;
+        for note in [12, 12, 13] {
+            h.start_playing_note(0.0, note);
+        }
+        let outputs: Vec<_> = h.sampler.channels[0]
+            .voices
+            .iter()
+            .map(|v| v.output)
+            .collect();
+        assert_eq!(outputs, vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn test_process_block_with_aux_outputs() {
+        let note = |n: u8| Note::new(n, 0);
+        let slice = |slice: usize| Command::StartPlayingSlice {
+            slice,
+            note: note(12 + slice as u8),
+            velocity: 1.0,
+            reverse: false,
+        };
+        // the second run records into another slot, going sample by sample
+        let recording = [
+            Command::SelectSlot { slot: 1 },
+            Command::StartRecording,
+            Command::SelectSlot { slot: 0 },
+        ];
+        for commands in [&[][..], &recording[..]] {
+            let mut h = EasyHost::default();
+            h.record(one_to_ten());
+            h.params.output_routing = OutputRouting::Slice;
+            h.params.output_count = 2;
+            let mut events: Vec<_> = commands.iter().map(|&c| (0, c)).collect();
+            events.extend([(0, slice(0)), (0, slice(1))]);
+            let mut output = vec![0.0; 10];
+            let mut aux_outputs = vec![vec![1.0; 10]; 2];
+            h.sampler.process_block_with_aux_outputs(
+                &[vec![0.0; 10]],
+                &[vec![0.0; 10]],
+                &mut [&mut output],
+                &mut aux_outputs,
+                &events,
+                &h.params,
+            );
+            assert_eq!(aux_outputs[0], one_to_ten());
+            assert!(aux_outputs[1].iter().any(|&x| x != 0.0));
+            let sum: Vec<f32> = aux_outputs[0]
+                .iter()
+                .zip(&aux_outputs[1])
+                .map(|(a, b)| a + b)
+                .collect();
+            assert_eq!(output, sum);
+        }
+    }
+
+    #[test]
+    fn test_process_block() {
+        let note = |n: u8| Note::new(n, 0);
+        let events = vec![
+            (0, Command::StartRecording),
+            (40, Command::StopRecording),
+            (
+                45,
+                Command::StartPlaying {
+                    pos: 0.0,
+                    note: note(60),
+                    velocity: 1.0,
+                    reverse: false,
+                },
+            ),
+            (
+                50,
+                Command::StartPlayingSlice {
+                    slice: 3,
+                    note: note(61),
+                    velocity: 0.5,
+                    reverse: false,
+                },
+            ),
+            (
+                60,
+                Command::SetNoteSpeed {
+                    note: note(60),
+                    speed: 1.5,
+                },
+            ),
+            (70, Command::SetReverse { reverse: true }),
+            (80, Command::SetSpeed { speed: 0.5 }),
+            (85, Command::SetPitch { pitch: 1.5 }),
+            (90, Command::StopPlaying { note: note(61) }),
+            (100, Command::SetReverse { reverse: false }),
+            (110, Command::StopPlaying { note: note(60) }),
+            (
+                130,
+                Command::StartPlaying {
+                    pos: 0.5,
+                    note: note(62),
+                    velocity: 1.0,
+                    reverse: true,
+                },
+            ),
+            (190, Command::SelectSlot { slot: 1 }),
+            (200, Command::CommitCapture),
+            (
+                210,
+                Command::StartPlaying {
+                    pos: 0.0,
+                    note: note(63),
+                    velocity: 1.0,
+                    reverse: false,
+                },
+            ),
+            (280, Command::StopPlaying { note: note(63) }),
+        ];
+        let frames = 320;
+        let inputs: Vec<Vec<f32>> = (0..2)
+            .map(|ch| (0..frames).map(|i| ((i * (ch + 3)) % 17) as f32).collect())
+            .collect();
+        let init_params = InitParams {
+            capture_buffer_samples: 100,
+            ..InitParams::default()
+        };
+
+        for (loop_mode, playback_mode) in [
+            (LoopMode::Loop, PlaybackMode::Resample),
+            (LoopMode::PingPong, PlaybackMode::Resample),
+            (LoopMode::PlayOnce, PlaybackMode::Resample),
+            (LoopMode::Loop, PlaybackMode::Granular),
+        ] {
+            let params = Params {
+                loop_mode,
+                playback_mode,
+                auto_passthru: true,
+                attack_samples: 3,
+                release_samples: 5,
+                grain_samples: 16,
+                loop_crossfade_samples: 4,
+                capture_length: TimeValue::samples(30.0),
+                ..base_params()
+            };
+
+            // sample by sample with the commands applied through the other entry points
+            let mut sampler = Sampler::new(2, &init_params);
+            let mut p = params.clone();
+            let mut expected = vec![vec![0.0; frames]; 2];
+            for i in 0..frames {
+                // overrides from commands last until the end of the block
+                if i % 64 == 0 {
+                    p = params.clone();
+                }
+                for &(_, command) in events.iter().filter(|(offset, _)| *offset == i) {
+                    match command {
+                        Command::StartPlaying {
+                            pos,
+                            note,
+                            velocity,
+                            reverse,
+                        } => sampler.start_playing(pos, note, velocity, reverse, &p),
+                        Command::StartPlayingSlice {
+                            slice,
+                            note,
+                            velocity,
+                            reverse,
+                        } => sampler.start_playing_slice(slice, note, velocity, reverse, &p),
+                        Command::StartPlayingWhole {
+                            note,
+                            velocity,
+                            reverse,
+                        } => sampler.start_playing_whole(note, velocity, reverse, &p),
+                        Command::StopPlaying { note } => sampler.stop_playing(note, &p),
+                        Command::StartRecording => {
+                            sampler.start_recording(&p);
+                        }
+                        Command::StopRecording => sampler.stop_recording(&p),
+                        Command::CommitCapture => {
+                            sampler.commit_capture(&p);
+                        }
+                        Command::SetNoteSpeed { note, speed } => {
+                            sampler.set_note_speed(note, speed)
+                        }
+                        Command::SetSpeed { speed } => p.speed = speed,
+                        Command::SetPitch { pitch } => p.pitch = pitch,
+                        Command::SetReverse { reverse } => {
+                            p.reverse_speed = if reverse { -1.0 } else { 1.0 }
+                        }
+                        Command::SelectSlot { slot } => p.sample_id = slot,
+                    }
+                }
+                let mut x = [inputs[0][i], inputs[1][i]];
+                let [a, b] = &mut x;
+                sampler.process_frame(&mut [a, b], &p);
+                expected[0][i] = x[0];
+                expected[1][i] = x[1];
+            }
+
+            // in blocks that don't line up with the events
+            let mut sampler = Sampler::new(2, &init_params);
+            let mut outputs = vec![vec![0.0; frames]; 2];
+            for start in (0..frames).step_by(64) {
+                let end = (start + 64).min(frames);
+                let block_events: Vec<_> = events
+                    .iter()
+                    .filter(|(offset, _)| (start..end).contains(offset))
+                    .map(|&(offset, command)| (offset - start, command))
+                    .collect();
+                let block_inputs: Vec<&[f32]> =
+                    inputs.iter().map(|input| &input[start..end]).collect();
+                let mut block_outputs: Vec<&mut [f32]> = outputs
+                    .iter_mut()
+                    .map(|output| &mut output[start..end])
+                    .collect();
+                sampler.process_block(&block_inputs, &mut block_outputs, &block_events, &params);
+            }
+            assert_eq!(outputs, expected, "{:?} {:?}", loop_mode, playback_mode);
+            assert!(outputs[0][45..110].iter().any(|&x| x != 0.0));
+        }
+    }
+
     #[test]
     fn test_updating_speed() {
         let mut h = EasyHost::default();
@@ -84,6 +84,17 @@ pub fn calc_quarter_notes_per_bar(transport: &common_types::Transport) -> f32 {
     quarter_notes_per_bar
 }
 
+// samples since the last bar line at or before the transport position
+pub fn calc_samples_since_bar(transport: &common_types::Transport) -> usize {
+    let bar = calc_samples_per_bar(transport);
+    if bar <= 0.0 {
+        return 0;
+    }
+    let pos = transport.pos_samples;
+    let since = (pos - (pos / bar).floor() * bar).round() as usize;
+    since % (bar.round() as usize).max(1)
+}
+
 pub fn calc_samples_per_quarter_note(transport: &common_types::Transport) -> f32 {
     let samples_per_minute = transport.sample_rate * 60.0;
     let samples_per_quarter_note = samples_per_minute / transport.tempo;
@@ -1,21 +1,51 @@
 use crate::clip::Clip;
-use crate::common_types::Note;
-use crate::volume::Volume;
+use crate::common_types::{Note, Params};
+use crate::envelope::Envelope;
+use crate::grain::Granulator;
 
 #[derive(Clone, Debug)]
 pub struct Voice {
+    pub id: usize,
     pub note: Note,
+    pub slot: usize,
     pub loop_start_percent: f32,
     pub played: f32,
     pub clip2: Clip,
-    pub volume: Volume,
+    pub envelope: Envelope,
+    pub granulator: Granulator,
     pub finished: bool,
     pub finished_at: usize,
+    // voice is fading out quickly to make room for a new one
+    pub stolen: bool,
     pub is_at_zero_crossing: bool,
     pub last_sample_value: f32,
     pub speed: f32,
+    // aux output the voice is also mixed into
+    pub output: Option<usize>,
+    // plays backwards regardless of the global reverse, which flips it again
+    pub reversed: bool,
+    // plays the whole buffer, ignoring the loop length param
+    pub whole: bool,
 
     // this is only used by the UI to show loop points
     // its hack/workaround for not having loop information easily available
     pub last_sample_index: usize,
 }
+
+impl Voice {
+    pub fn direction(&self) -> f32 {
+        if self.reversed {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
+    pub fn loop_length(&self, data_len: usize, params: &Params) -> f32 {
+        if self.whole {
+            data_len as f32
+        } else {
+            params.loop_length(data_len)
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::audio_file::{
+    decode_samples, invalid_data, is_valid_sample_rate, AudioData, SampleEncoding,
+};
+use crate::common_types::WavFormat;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IEEE_FLOAT: u16 = 3;
+// actual format is in the first two bytes of the sub format GUID
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+impl WavFormat {
+    fn bytes_per_sample(&self) -> usize {
+        match self {
+            WavFormat::Float32 => 4,
+            WavFormat::Pcm24 => 3,
+            WavFormat::Pcm16 => 2,
+        }
+    }
+}
+
+fn write_sample<W: Write>(w: &mut W, x: f32, format: WavFormat) -> io::Result<()> {
+    match format {
+        WavFormat::Float32 => w.write_all(&x.to_le_bytes()),
+        WavFormat::Pcm24 => {
+            let x = (x.clamp(-1.0, 1.0) * 8388607.0).round() as i32;
+            w.write_all(&x.to_le_bytes()[..3])
+        }
+        WavFormat::Pcm16 => {
+            let x = (x.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+            w.write_all(&x.to_le_bytes())
+        }
+    }
+}
+
+/// Writes channels as an interleaved WAV stream. Shorter channels are padded with silence.
+pub fn write<W: Write>(
+    mut w: W,
+    channels: &[&[f32]],
+    sample_rate: u32,
+    format: WavFormat,
+) -> io::Result<()> {
+    let frames = channels.iter().map(|data| data.len()).max().unwrap_or(0);
+    let channel_count = channels.len() as u16;
+    let bytes_per_sample = format.bytes_per_sample();
+    let block_align = channel_count as usize * bytes_per_sample;
+    let data_size = (frames * block_align) as u32;
+    // non-PCM formats carry cbSize in fmt and need a fact chunk
+    let (format_tag, fmt_size, fact_size) = match format {
+        WavFormat::Float32 => (FORMAT_IEEE_FLOAT, 18u32, 12u32),
+        WavFormat::Pcm24 | WavFormat::Pcm16 => (FORMAT_PCM, 16, 0),
+    };
+    let riff_size = 4 + (8 + fmt_size) + fact_size + (8 + data_size) + data_size % 2;
+
+    w.write_all(b"RIFF")?;
+    w.write_all(&riff_size.to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    w.write_all(b"fmt ")?;
+    w.write_all(&fmt_size.to_le_bytes())?;
+    w.write_all(&format_tag.to_le_bytes())?;
+    w.write_all(&channel_count.to_le_bytes())?;
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    w.write_all(&(block_align as u16).to_le_bytes())?;
+    w.write_all(&(bytes_per_sample as u16 * 8).to_le_bytes())?;
+    if fmt_size == 18 {
+        w.write_all(&0u16.to_le_bytes())?;
+    }
+    if fact_size > 0 {
+        w.write_all(b"fact")?;
+        w.write_all(&4u32.to_le_bytes())?;
+        w.write_all(&(frames as u32).to_le_bytes())?;
+    }
+
+    w.write_all(b"data")?;
+    w.write_all(&data_size.to_le_bytes())?;
+    for i in 0..frames {
+        for data in channels {
+            write_sample(&mut w, data.get(i).cloned().unwrap_or(0.0), format)?;
+        }
+    }
+    // chunks are word aligned
+    if data_size % 2 == 1 {
+        w.write_all(&[0])?;
+    }
+    w.flush()
+}
+
+pub fn export(
+    path: &Path,
+    channels: &[&[f32]],
+    sample_rate: u32,
+    format: WavFormat,
+) -> io::Result<()> {
+    write(
+        BufWriter::new(File::create(path)?),
+        channels,
+        sample_rate,
+        format,
+    )
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> io::Result<u16> {
+    bytes
+        .get(at..at + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| invalid_data("truncated WAV file"))
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> io::Result<u32> {
+    bytes
+        .get(at..at + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid_data("truncated WAV file"))
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<AudioData> {
+    if bytes.get(0..4) != Some(b"RIFF") || bytes.get(8..12) != Some(b"WAVE") {
+        return Err(invalid_data("not a WAV file"));
+    }
+    // format tag, channels, sample rate and bits per sample
+    let mut fmt: Option<(u16, usize, u32, usize)> = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let size = u32_le(bytes, at + 4)? as usize;
+        let body = at + 8;
+        match id {
+            b"fmt " => {
+                let mut tag = u16_le(bytes, body)?;
+                if tag == FORMAT_EXTENSIBLE {
+                    tag = u16_le(bytes, body + 24)?;
+                }
+                fmt = Some((
+                    tag,
+                    u16_le(bytes, body + 2)? as usize,
+                    u32_le(bytes, body + 4)?,
+                    u16_le(bytes, body + 14)? as usize,
+                ));
+            }
+            b"data" => {
+                let (tag, channel_count, sample_rate, bits) =
+                    fmt.ok_or_else(|| invalid_data("WAV data before fmt chunk"))?;
+                let encoding = match (tag, bits) {
+                    (FORMAT_PCM, 8) => SampleEncoding::UnsignedPcm8,
+                    (FORMAT_PCM, _) => SampleEncoding::Pcm { big_endian: false },
+                    (FORMAT_IEEE_FLOAT, _) => SampleEncoding::Float { big_endian: false },
+                    _ => return Err(invalid_data("unsupported WAV format")),
+                };
+                if !is_valid_sample_rate(sample_rate as f32) {
+                    return Err(invalid_data("invalid WAV sample rate"));
+                }
+                // recorders that were interrupted may leave the size unset
+                let end = (body + size).min(bytes.len());
+                return Ok(AudioData {
+                    channels: decode_samples(&bytes[body..end], channel_count, bits, encoding)?,
+                    sample_rate: sample_rate as f32,
+                });
+            }
+            _ => (),
+        }
+        // chunks are word aligned
+        at = body + size + size % 2;
+    }
+    Err(invalid_data("WAV file has no data chunk"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_decode_written() {
+        let left = [0.5, -0.25, 0.0];
+        let right = [1.0, -1.0, 0.125];
+        for format in [WavFormat::Float32, WavFormat::Pcm24, WavFormat::Pcm16] {
+            let mut out = vec![];
+            write(&mut out, &[&left, &right], 22050, format).unwrap();
+            let audio = decode(&out).unwrap();
+            assert_eq!(audio.sample_rate, 22050.0);
+            assert_eq!(audio.channels.len(), 2);
+            for (decoded, expected) in audio.channels.iter().zip([&left, &right]) {
+                for (x, y) in decoded.iter().zip(expected.iter()) {
+                    assert!((x - y).abs() < 1e-4, "{:?} {:?}", format, audio);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_rate() {
+        for rate in [0, 1, 999, 1_000_001, 100_000_000] {
+            let mut out = vec![];
+            write(&mut out, &[&[0.5]], rate, WavFormat::Pcm16).unwrap();
+            let err = decode(&out).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_write_pcm16() {
+        let mut out = vec![];
+        let left = [0.0, 1.0, -1.0];
+        let right = [0.5];
+        write(&mut out, &[&left, &right], 48000, WavFormat::Pcm16).unwrap();
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(u32_at(&out, 4) as usize, out.len() - 8);
+        assert_eq!(&out[8..16], b"WAVEfmt ");
+        assert_eq!(u16_at(&out, 20), FORMAT_PCM);
+        assert_eq!(u16_at(&out, 22), 2);
+        assert_eq!(u32_at(&out, 24), 48000);
+        assert_eq!(u16_at(&out, 32), 4);
+        assert_eq!(u16_at(&out, 34), 16);
+        assert_eq!(&out[36..40], b"data");
+        assert_eq!(u32_at(&out, 40), 12);
+        let samples: Vec<i16> = out[44..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples, vec![0, 16384, 32767, 0, -32767, 0]);
+    }
+
+    #[test]
+    fn test_write_float_and_pcm24() {
+        let data = [0.25, -0.5, 0.125];
+        let mut out = vec![];
+        write(&mut out, &[&data], 44100, WavFormat::Float32).unwrap();
+        assert_eq!(u16_at(&out, 20), FORMAT_IEEE_FLOAT);
+        assert_eq!(&out[38..42], b"fact");
+        assert_eq!(u32_at(&out, 46), 3);
+        assert_eq!(&out[50..54], b"data");
+        let samples: Vec<f32> = out[58..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(samples, data.to_vec());
+
+        let mut out = vec![];
+        write(&mut out, &[&data], 44100, WavFormat::Pcm24).unwrap();
+        // 9 data bytes are padded to an even chunk size
+        assert_eq!(u32_at(&out, 40), 9);
+        assert_eq!(out.len(), 44 + 10);
+        assert_eq!(u32_at(&out, 4) as usize, out.len() - 8);
+        assert_eq!(&out[44..47], &[0x00, 0x00, 0x20]);
+    }
+}
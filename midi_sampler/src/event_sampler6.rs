@@ -17,6 +17,10 @@ pub struct Params {
     pub pos_samples: Option<i64>,
     pub pos_seconds: Option<f64>,
     pub tempo: Option<f64>,
+    pub record_note: u8,
+    // slice_count notes from here play from evenly spaced positions of the clip
+    pub slice_base_note: u8,
+    pub slice_count: u8,
 }
 
 #[derive(Debug)]
@@ -117,19 +121,30 @@ struct FrameActions {
     stop: bool,
 }
 
-const BASE_NOTE: u8 = 12;
-
-fn partition_actions<S>(ev: Vec<NoteEvent<S>>) -> (Vec<NoteEvent<S>>, FrameActions) {
+fn partition_actions<S>(
+    ev: Vec<NoteEvent<S>>,
+    params: &Params,
+) -> (Vec<NoteEvent<S>>, FrameActions) {
     let mut out_events = vec![];
     let mut frame_actions = FrameActions::default();
+    let slice_count = params.slice_count.max(1) as u16;
+    let slice = |note: u8| {
+        (note as u16)
+            .checked_sub(params.slice_base_note as u16)
+            .filter(|&slice| slice < slice_count)
+    };
     for e in ev {
         match e {
-            NoteEvent::NoteOn { note, .. } if note == 0 => frame_actions.start_recording = true,
-            NoteEvent::NoteOff { note, .. } if note == 0 => frame_actions.stop_recording = true,
-            NoteEvent::NoteOn { note, .. } if note >= BASE_NOTE && note < BASE_NOTE + 16 => {
-                frame_actions.play_from = Some((note - BASE_NOTE) as f32 / 16.0)
+            NoteEvent::NoteOn { note, .. } if note == params.record_note => {
+                frame_actions.start_recording = true
+            }
+            NoteEvent::NoteOff { note, .. } if note == params.record_note => {
+                frame_actions.stop_recording = true
+            }
+            NoteEvent::NoteOn { note, .. } if slice(note).is_some() => {
+                frame_actions.play_from = slice(note).map(|slice| slice as f32 / slice_count as f32)
             }
-            NoteEvent::NoteOff { note, .. } if note >= BASE_NOTE && note < BASE_NOTE + 16 => {
+            NoteEvent::NoteOff { note, .. } if slice(note).is_some() => {
                 frame_actions.stop = true;
             }
             _ => {
@@ -345,7 +360,7 @@ where
         for e in &events {
             nih_warn!("{:<8} IN  EVENT {:?}", self.time, note_from_event(e));
         }
-        let (mut events, actions) = partition_actions(events);
+        let (mut events, actions) = partition_actions(events, params);
         self.process_recording(&actions, &events);
         self.process_playback(&actions, &mut output);
         if params.passthru {
@@ -22,6 +22,12 @@ pub struct MIDISampler {
 struct MIDISamplerParams {
     #[id = "passthru"]
     pub passthru: BoolParam,
+    #[id = "record_note"]
+    pub record_note: IntParam,
+    #[id = "slice_base_note"]
+    pub slice_base_note: IntParam,
+    #[id = "slice_count"]
+    pub slice_count: IntParam,
     //    #[id = "speed"]
     //    pub speed: FloatParam,
     //    #[id = "fade time"]
@@ -32,6 +38,13 @@ impl Default for MIDISamplerParams {
     fn default() -> Self {
         Self {
             passthru: BoolParam::new("Pass through", true),
+            record_note: IntParam::new("Record note", 0, IntRange::Linear { min: 0, max: 127 }),
+            slice_base_note: IntParam::new(
+                "Slice base note",
+                12,
+                IntRange::Linear { min: 0, max: 127 },
+            ),
+            slice_count: IntParam::new("Slice count", 16, IntRange::Linear { min: 1, max: 64 }),
         }
     }
 }
@@ -55,6 +68,9 @@ impl MIDISampler {
             pos_samples: context.transport().pos_samples(),
             pos_seconds: context.transport().pos_seconds(),
             tempo: context.transport().tempo,
+            record_note: self.params.record_note.value() as u8,
+            slice_base_note: self.params.slice_base_note.value() as u8,
+            slice_count: self.params.slice_count.value() as u8,
         }
     }
 }